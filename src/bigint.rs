@@ -0,0 +1,586 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
+
+/// An arbitrary-precision integer, stored as a sign and a little-endian
+/// magnitude in base 2^32 without trailing zero limbs. Zero has an empty
+/// magnitude and is never negative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+fn trim(mut magnitude: Vec<u32>) -> Vec<u32> {
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+    magnitude
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &limb) in long.iter().enumerate() {
+        let sum = limb as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// Subtracts `b` from `a`, which must not be smaller.
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let diff = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        result.push(diff as u32);
+        borrow = (diff < 0) as i64;
+    }
+    trim(result)
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let product = x as u64 * y as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    trim(result)
+}
+
+/// Multiplies `a` by `factor` and adds `addend`, in place.
+fn mul_add_small(a: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for limb in a.iter_mut() {
+        let product = *limb as u64 * factor as u64 + carry;
+        *limb = product as u32;
+        carry = product >> 32;
+    }
+    if carry > 0 {
+        a.push(carry as u32);
+    }
+}
+
+fn div_rem_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+    for (i, &limb) in a.iter().enumerate().rev() {
+        let current = (remainder << 32) | limb as u64;
+        quotient[i] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    (trim(quotient), remainder as u32)
+}
+
+fn shl_bits(a: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return a.to_vec();
+    }
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u32;
+    for &limb in a {
+        result.push((limb << shift) | carry);
+        carry = limb >> (32 - shift);
+    }
+    result.push(carry);
+    result
+}
+
+fn shr_bits(a: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return trim(a.to_vec());
+    }
+    let mut result = vec![0u32; a.len()];
+    for i in 0..a.len() {
+        let high = a.get(i + 1).map_or(0, |&next| next << (32 - shift));
+        result[i] = (a[i] >> shift) | high;
+    }
+    trim(result)
+}
+
+/// Divides magnitudes with Knuth's algorithm D. The divisor must be non-zero.
+fn div_rem_magnitude(u: &[u32], v: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(u, v) == Ordering::Less {
+        return (Vec::new(), u.to_vec());
+    }
+    if v.len() == 1 {
+        let (quotient, remainder) = div_rem_small(u, v[0]);
+        return (quotient, trim(vec![remainder]));
+    }
+
+    // Normalize so that the top limb of the divisor has its high bit set.
+    let shift = v[v.len() - 1].leading_zeros();
+    let v = shl_bits(v, shift);
+    let v = &v[..v.len() - usize::from(shift > 0)];
+    let mut u = shl_bits(u, shift);
+    if shift == 0 {
+        u.push(0);
+    }
+    let n = v.len();
+    let m = u.len() - n;
+    let mut quotient = vec![0u32; m];
+    let (v_top, v_next) = (v[n - 1] as u64, v[n - 2] as u64);
+    const BASE: u64 = 1 << 32;
+
+    for j in (0..m).rev() {
+        let numerator = ((u[j + n] as u64) << 32) | u[j + n - 1] as u64;
+        let mut q_hat = numerator / v_top;
+        let mut r_hat = numerator % v_top;
+        while q_hat >= BASE || q_hat * v_next > ((r_hat << 32) | u[j + n - 2] as u64) {
+            q_hat -= 1;
+            r_hat += v_top;
+            if r_hat >= BASE {
+                break;
+            }
+        }
+
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let product = q_hat * v[i] as u64 + carry;
+            carry = product >> 32;
+            let diff = u[i + j] as i64 - borrow - (product & 0xffff_ffff) as i64;
+            u[i + j] = diff as u32;
+            borrow = (diff < 0) as i64;
+        }
+        let diff = u[j + n] as i64 - borrow - carry as i64;
+        u[j + n] = diff as u32;
+
+        if diff < 0 {
+            // The estimate was one too large, so add the divisor back.
+            q_hat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                u[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            u[j + n] = u[j + n].wrapping_add(carry as u32);
+        }
+        quotient[j] = q_hat as u32;
+    }
+    (trim(quotient), shr_bits(&u[..n], shift))
+}
+
+impl BigInt {
+    fn new(negative: bool, magnitude: Vec<u32>) -> Self {
+        let magnitude = trim(magnitude);
+        BigInt {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Returns the value as an `i64` if it fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &limb| (acc << 32) | limb as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    /// Returns the nearest `f64`, or an infinity if the value is out of range.
+    pub fn to_f64(&self) -> f64 {
+        // Going through the decimal representation gets the rounding right.
+        self.to_string().parse().unwrap()
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::new(false, self.magnitude.clone())
+    }
+
+    /// The number of bits in the magnitude, not counting leading zeros.
+    pub fn bits(&self) -> u64 {
+        match self.magnitude.last() {
+            Some(top) => self.magnitude.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    /// The base 2 logarithm of the magnitude, or negative infinity for zero.
+    pub fn log2(&self) -> f64 {
+        // The top 64 bits are as many as an f64 can make use of.
+        let (top, rest) = match self.magnitude.as_slice() {
+            [] => return f64::NEG_INFINITY,
+            [low] => (*low as u64, 0),
+            [.., low, high] => (
+                ((*high as u64) << 32) | *low as u64,
+                self.magnitude.len() - 2,
+            ),
+        };
+        (top as f64).log2() + 32.0 * rest as f64
+    }
+
+    /// Multiplies by 2 to the power of `bits`.
+    pub fn shl(&self, bits: u64) -> BigInt {
+        let mut magnitude = vec![0u32; (bits / 32) as usize];
+        magnitude.extend(shl_bits(&self.magnitude, (bits % 32) as u32));
+        BigInt::new(self.negative, magnitude)
+    }
+
+    pub fn pow(&self, mut exponent: u64) -> BigInt {
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    /// The square root of the magnitude, rounded down.
+    pub fn sqrt(&self) -> BigInt {
+        let n = self.abs();
+        if n.is_zero() {
+            return n;
+        }
+        // Newton's method, starting from a power of two at least as large as
+        // the root, decreases monotonically until it reaches the answer.
+        let mut root = BigInt::from(1).shl(n.bits().div_ceil(2));
+        loop {
+            let next = (&root + &n.div_rem(&root).0).div_rem(&BigInt::from(2)).0;
+            if next >= root {
+                return root;
+            }
+            root = next;
+        }
+    }
+
+    /// The greatest common divisor, which is never negative.
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let remainder = a.div_rem(&b).1;
+            a = b;
+            b = remainder;
+        }
+        a
+    }
+
+    /// Divides by `other`, truncating towards zero, and returns the quotient
+    /// and the remainder, which has the sign of `self`.
+    ///
+    /// Panics if `other` is zero.
+    pub fn div_rem(&self, other: &BigInt) -> (BigInt, BigInt) {
+        assert!(!other.is_zero(), "division of a BigInt by zero");
+        let (quotient, remainder) = div_rem_magnitude(&self.magnitude, &other.magnitude);
+        (
+            BigInt::new(self.negative != other.negative, quotient),
+            BigInt::new(self.negative, remainder),
+        )
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(v: i64) -> Self {
+        let magnitude = v.unsigned_abs();
+        BigInt::new(v < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => cmp_magnitude(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.magnitude.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(
+                self.negative,
+                add_magnitude(&self.magnitude, &other.magnitude),
+            );
+        }
+        match cmp_magnitude(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::new(
+                other.negative,
+                sub_magnitude(&other.magnitude, &self.magnitude),
+            ),
+            _ => BigInt::new(
+                self.negative,
+                sub_magnitude(&self.magnitude, &other.magnitude),
+            ),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(
+            self.negative != other.negative,
+            mul_magnitude(&self.magnitude, &other.magnitude),
+        )
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Split into base 10^9 chunks, least significant first.
+        let mut chunks = Vec::new();
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, remainder) = div_rem_small(&magnitude, 1_000_000_000);
+            chunks.push(remainder);
+            magnitude = quotient;
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// The number of digits in `radix` that always fit in a limb, together with
+/// `radix` raised to that number.
+fn chunk_size(radix: u32) -> (usize, u32) {
+    let mut size = 1;
+    let mut power = radix;
+    while let Some(next) = power.checked_mul(radix) {
+        size += 1;
+        power = next;
+    }
+    (size, power)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    /// Parses a decimal integer with an optional sign.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseBigIntError);
+        }
+        let mut magnitude = Vec::new();
+        for chunk in digits.as_bytes().chunks(9) {
+            let value = chunk
+                .iter()
+                .fold(0u32, |acc, &digit| acc * 10 + (digit - b'0') as u32);
+            mul_add_small(&mut magnitude, 10u32.pow(chunk.len() as u32), value);
+        }
+        Ok(BigInt::new(negative, magnitude))
+    }
+}
+
+impl BigInt {
+    /// Parses an integer with an optional sign in the given radix, which must
+    /// be between 2 and 36.
+    pub fn from_str_radix(s: &str, radix: u32) -> Result<Self, ParseBigIntError> {
+        if radix == 10 {
+            return s.parse();
+        }
+        let (negative, digits) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return Err(ParseBigIntError);
+        }
+        // Take as many digits at a time as fit in a limb.
+        let (chunk_size, _) = chunk_size(radix);
+        let mut magnitude = Vec::new();
+        for chunk in digits.as_bytes().chunks(chunk_size) {
+            let value = chunk.iter().fold(0u32, |acc, &digit| {
+                acc * radix + (digit as char).to_digit(radix).unwrap()
+            });
+            mul_add_small(&mut magnitude, radix.pow(chunk.len() as u32), value);
+        }
+        Ok(BigInt::new(negative, magnitude))
+    }
+
+    /// Formats the integer in the given radix, which must be between 2 and
+    /// 36, using lowercase letters for digits above 9.
+    pub fn to_str_radix(&self, radix: u32) -> String {
+        if radix == 10 || self.is_zero() {
+            return self.to_string();
+        }
+        let (chunk_size, chunk_radix) = chunk_size(radix);
+        let mut digits = Vec::new();
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, mut remainder) = div_rem_small(&magnitude, chunk_radix);
+            magnitude = quotient;
+            // Every chunk but the most significant is padded with zeros.
+            for _ in 0..chunk_size {
+                if remainder == 0 && magnitude.is_empty() {
+                    break;
+                }
+                digits.push(std::char::from_digit(remainder % radix, radix).unwrap());
+                remainder /= radix;
+            }
+        }
+        if self.negative {
+            digits.push('-');
+        }
+        digits.iter().rev().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BigInt;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = big("99999999999");
+        assert_eq!((&a * &a).to_string(), "9999999999800000000001");
+        assert_eq!(
+            (&big("-18446744073709551616") + &big("1")).to_string(),
+            "-18446744073709551615"
+        );
+        assert_eq!((&big("5") - &big("12")).to_string(), "-7");
+        assert_eq!(big("-0"), BigInt::from(0));
+        assert!(big("-100000000000000000000") < big("-99999999999999999999"));
+    }
+
+    #[test]
+    fn division() {
+        let n = big("123456789012345678901234567890123456789");
+        let d = big("-9876543210987654321");
+        let (q, r) = n.div_rem(&d);
+        assert_eq!(q.to_string(), "-12499999886093750001");
+        assert_eq!(&(&q * &d) + &r, n);
+        assert!(!r.is_negative() && r < big("9876543210987654321"));
+
+        let (q, r) = big("-7").div_rem(&big("2"));
+        assert_eq!((q, r), (BigInt::from(-3), BigInt::from(-1)));
+    }
+
+    #[test]
+    fn square_root() {
+        assert_eq!(BigInt::from(0).sqrt(), BigInt::from(0));
+        assert_eq!(BigInt::from(1).sqrt(), BigInt::from(1));
+        assert_eq!(BigInt::from(99).sqrt(), BigInt::from(9));
+        let root = big("123456789012345678901");
+        assert_eq!((&root * &root).sqrt(), root);
+        assert_eq!(
+            (&(&root * &root) - &BigInt::from(1)).sqrt(),
+            &root - &BigInt::from(1)
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(BigInt::from_str_radix("-ff", 16), Ok(BigInt::from(-255)));
+        assert_eq!(
+            BigInt::from_str_radix(
+                "10000000000000000000000000000000000000000000000000000000000000000",
+                2
+            ),
+            Ok(big("18446744073709551616"))
+        );
+        assert!(BigInt::from_str_radix("12", 2).is_err());
+        assert_eq!(BigInt::from(-255).to_str_radix(16), "-ff");
+        assert_eq!(
+            big("18446744073709551616").to_str_radix(8),
+            "2000000000000000000000"
+        );
+        let value = &big("3").pow(1000) - &BigInt::from(1);
+        for radix in [2, 3, 7, 16, 36] {
+            let digits = value.to_str_radix(radix);
+            assert_eq!(BigInt::from_str_radix(&digits, radix), Ok(value.clone()));
+        }
+        assert_eq!(
+            big("3").pow(40).to_str_radix(3),
+            format!("1{}", "0".repeat(40))
+        );
+        assert_eq!(big("1").log2(), 0.0);
+        assert_eq!(big("18446744073709551616").log2(), 64.0);
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(BigInt::from(i64::MAX).to_i64(), Some(i64::MAX));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-9223372036854775809").to_i64(), None);
+        assert_eq!(big("1000000000000000000000").to_f64(), 1e21);
+    }
+}
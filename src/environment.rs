@@ -1,97 +1,144 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::rc::Rc;
 
-use crate::expression::Expression;
+use crate::error::{ErrorKind, SchemeError};
+use crate::eval::{apply, is_true};
+use crate::expression::{Expression, Pair};
 use crate::number::Number;
+use crate::symbol::Symbol;
 
+struct Frame {
+    bindings: RefCell<HashMap<Symbol, Expression>>,
+    parent: Option<Environment>,
+}
+
+/// A shared handle to a frame of bindings. Cloning an environment is cheap and
+/// yields a handle to the same frame, so closures can capture and outlive it.
+#[derive(Clone)]
 pub struct Environment {
-    stack: Vec<HashMap<String, Expression>>,
+    frame: Rc<Frame>,
 }
 
 impl Environment {
     fn new() -> Self {
-        Self { stack: Vec::new() }
+        Self::with_parent(None)
+    }
+    fn with_parent(parent: Option<Environment>) -> Self {
+        Self {
+            frame: Rc::new(Frame {
+                bindings: RefCell::new(HashMap::new()),
+                parent,
+            }),
+        }
     }
-    pub fn push(&mut self) {
-        self.stack.push(HashMap::new());
+    /// Creates a new, empty frame whose parent is this environment.
+    pub fn extend(&self) -> Self {
+        Self::with_parent(Some(self.clone()))
     }
-    pub fn pop(&mut self) {
-        self.stack.pop();
+    /// Binds `key` in this frame, shadowing any binding in the parent frames.
+    pub fn define(&self, key: Symbol, value: Expression) {
+        self.frame.bindings.borrow_mut().insert(key, value);
     }
-    pub fn insert(&mut self, key: String, value: Expression) {
-        self.stack.last_mut().unwrap().insert(key, value);
+    /// Updates the nearest existing binding of `key`. Unlike `define`, this
+    /// never creates a binding and fails if `key` is unbound.
+    pub fn set(&self, key: &Symbol, value: Expression) -> Result<(), SchemeError> {
+        let mut env = self;
+        loop {
+            if let Some(slot) = env.frame.bindings.borrow_mut().get_mut(key) {
+                *slot = value;
+                return Ok(());
+            }
+            match &env.frame.parent {
+                Some(parent) => env = parent,
+                None => return Err(ErrorKind::UnboundVariable(key.to_string()).into()),
+            }
+        }
     }
-    pub fn lookup(&self, key: &String) -> Option<&Expression> {
-        self.stack.iter().rev().find_map(|m| m.get(key))
+    pub fn lookup(&self, key: &Symbol) -> Option<Expression> {
+        let mut env = self;
+        loop {
+            if let Some(value) = env.frame.bindings.borrow().get(key) {
+                return Some(value.clone());
+            }
+            env = env.frame.parent.as_ref()?;
+        }
     }
 }
 
-fn builtin_add(args: Vec<Expression>) -> Result<Expression, String> {
-    Ok(Expression::NumberLiteral(args.iter().try_fold(
-        Number::from(0),
-        |acc, v| match v {
-            Expression::NumberLiteral(i) => Ok(acc + *i),
-            _ => Err("Expecting number"),
-        },
-    )?))
+fn expect_number(value: &Expression) -> Result<Number, SchemeError> {
+    match value {
+        Expression::NumberLiteral(n) => Ok(n.clone()),
+        _ => Err(SchemeError::wrong_type("number", value)),
+    }
 }
 
-fn builtin_mul(args: Vec<Expression>) -> Result<Expression, String> {
-    Ok(Expression::NumberLiteral(args.iter().try_fold(
-        Number::from(1),
-        |acc, v| match v {
-            Expression::NumberLiteral(i) => Ok(acc * *i),
-            _ => Err("Expecting number"),
-        },
-    )?))
+fn expect_real(value: &Expression) -> Result<Number, SchemeError> {
+    match value {
+        Expression::NumberLiteral(n) if n.is_real() => Ok(n.clone()),
+        _ => Err(SchemeError::wrong_type("real number", value)),
+    }
 }
 
-fn builtin_sub(args: Vec<Expression>) -> Result<Expression, String> {
-    let mut arg_iter = args.iter();
-    let first_num = match arg_iter.next() {
-        Some(Expression::NumberLiteral(num)) => num,
-        Some(_) => return Err("Expecting number".to_string()),
-        None => return Err("Incorrect argument count in call (-)".to_string()),
-    };
+fn builtin_add(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    Ok(Expression::NumberLiteral(
+        args.iter().try_fold(Number::from(0), |acc, v| {
+            Ok::<_, SchemeError>(acc + expect_number(v)?)
+        })?,
+    ))
+}
+
+fn builtin_mul(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    Ok(Expression::NumberLiteral(
+        args.iter().try_fold(Number::from(1), |acc, v| {
+            Ok::<_, SchemeError>(acc * expect_number(v)?)
+        })?,
+    ))
+}
+
+fn builtin_sub(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, None, "-")?;
+    let first_num = expect_number(&args[0])?;
     if args.len() == 1 {
-        return Ok(Expression::NumberLiteral(-*first_num));
+        return Ok(Expression::NumberLiteral(-first_num));
     }
-    Ok(Expression::NumberLiteral(arg_iter.try_fold(
-        *first_num,
-        |acc, v| match v {
-            Expression::NumberLiteral(i) => Ok(acc - *i),
-            _ => Err("Expecting number"),
-        },
-    )?))
+    Ok(Expression::NumberLiteral(
+        args[1..].iter().try_fold(first_num, |acc, v| {
+            Ok::<_, SchemeError>(acc - expect_number(v)?)
+        })?,
+    ))
 }
 
-fn builtin_div(args: Vec<Expression>) -> Result<Expression, String> {
-    let mut arg_iter = args.iter();
-    let first_num = match arg_iter.next() {
-        Some(Expression::NumberLiteral(num)) => num,
-        Some(_) => return Err("Expecting number".to_string()),
-        None => return Err("Incorrect argument count in call (/)".to_string()),
+fn builtin_div(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, None, "/")?;
+    let first_num = expect_number(&args[0])?;
+    let divide = |acc: Number, v: &Expression| {
+        acc.checked_div(&expect_number(v)?)
+            .ok_or_else(|| SchemeError::from(ErrorKind::DivisionByZero))
     };
-    Ok(Expression::NumberLiteral(arg_iter.try_fold(
-        *first_num,
-        |acc, v| match v {
-            Expression::NumberLiteral(i) => Ok(acc / *i),
-            _ => Err("Expecting number"),
-        },
-    )?))
+    if args.len() == 1 {
+        return Ok(Expression::NumberLiteral(divide(
+            Number::from(1),
+            &args[0],
+        )?));
+    }
+    Ok(Expression::NumberLiteral(
+        args[1..].iter().try_fold(first_num, divide)?,
+    ))
 }
 
-fn extract_numbers(args: Vec<Expression>) -> Result<Vec<Number>, String> {
-    args.iter()
-        .map(|e| match e {
-            Expression::NumberLiteral(n) => Ok(n.clone()),
-            _ => Err("Expecting number".to_string()),
-        })
-        .collect()
+fn extract_numbers(args: Vec<Expression>) -> Result<Vec<Number>, SchemeError> {
+    args.iter().map(expect_number).collect()
 }
 
-fn builtin_greater_than(args: Vec<Expression>) -> Result<Expression, String> {
-    let nums = extract_numbers(args)?;
+fn extract_reals(args: Vec<Expression>) -> Result<Vec<Number>, SchemeError> {
+    args.iter().map(expect_real).collect()
+}
+
+fn builtin_greater_than(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let nums = extract_reals(args)?;
     if nums.len() <= 1 {
         return Ok(Expression::BooleanLiteral(true));
     }
@@ -103,8 +150,34 @@ fn builtin_greater_than(args: Vec<Expression>) -> Result<Expression, String> {
     Ok(Expression::BooleanLiteral(result))
 }
 
-fn builtin_less_than(args: Vec<Expression>) -> Result<Expression, String> {
-    let nums = extract_numbers(args)?;
+fn builtin_greater_or_equal(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let nums = extract_reals(args)?;
+    if nums.len() <= 1 {
+        return Ok(Expression::BooleanLiteral(true));
+    }
+    let result = nums
+        .iter()
+        .enumerate()
+        .skip(1)
+        .all(|(index, e)| nums[index - 1] >= *e);
+    Ok(Expression::BooleanLiteral(result))
+}
+
+fn builtin_less_or_equal(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let nums = extract_reals(args)?;
+    if nums.len() <= 1 {
+        return Ok(Expression::BooleanLiteral(true));
+    }
+    let result = nums
+        .iter()
+        .enumerate()
+        .skip(1)
+        .all(|(index, e)| nums[index - 1] <= *e);
+    Ok(Expression::BooleanLiteral(result))
+}
+
+fn builtin_less_than(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let nums = extract_reals(args)?;
     if nums.len() <= 1 {
         return Ok(Expression::BooleanLiteral(true));
     }
@@ -116,7 +189,7 @@ fn builtin_less_than(args: Vec<Expression>) -> Result<Expression, String> {
     Ok(Expression::BooleanLiteral(result))
 }
 
-fn builtin_equal(args: Vec<Expression>) -> Result<Expression, String> {
+fn builtin_equal(args: Vec<Expression>) -> Result<Expression, SchemeError> {
     let nums = extract_numbers(args)?;
     if nums.len() <= 1 {
         return Ok(Expression::BooleanLiteral(true));
@@ -129,41 +202,1205 @@ fn builtin_equal(args: Vec<Expression>) -> Result<Expression, String> {
     Ok(Expression::BooleanLiteral(result))
 }
 
-pub fn create_root_environment() -> Environment {
-    let mut root_env = Environment::new();
+fn builtin_is_exact(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "exact?")?;
+    Ok(Expression::BooleanLiteral(
+        expect_number(&args[0])?.is_exact(),
+    ))
+}
 
-    root_env.push();
+fn builtin_is_inexact(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "inexact?")?;
+    Ok(Expression::BooleanLiteral(
+        !expect_number(&args[0])?.is_exact(),
+    ))
+}
 
-    root_env.insert(
-        "+".to_string(),
-        Expression::BuiltinProcedure(Rc::new(builtin_add)),
-    );
-    root_env.insert(
-        "-".to_string(),
-        Expression::BuiltinProcedure(Rc::new(builtin_sub)),
-    );
-    root_env.insert(
-        "*".to_string(),
-        Expression::BuiltinProcedure(Rc::new(builtin_mul)),
-    );
-    root_env.insert(
-        "/".to_string(),
-        Expression::BuiltinProcedure(Rc::new(builtin_div)),
-    );
-    root_env.insert("#t".to_string(), Expression::BooleanLiteral(true));
-    root_env.insert("#f".to_string(), Expression::BooleanLiteral(false));
-    root_env.insert(
-        ">".to_string(),
-        Expression::BuiltinProcedure(Rc::new(builtin_greater_than)),
-    );
-    root_env.insert(
-        "<".to_string(),
-        Expression::BuiltinProcedure(Rc::new(builtin_less_than)),
-    );
-    root_env.insert(
-        "=".to_string(),
-        Expression::BuiltinProcedure(Rc::new(builtin_equal)),
+fn builtin_exact(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "exact")?;
+    match expect_number(&args[0])?.to_exact() {
+        Some(n) => Ok(Expression::NumberLiteral(n)),
+        None => Err(SchemeError::wrong_type("finite number", &args[0])),
+    }
+}
+
+fn builtin_inexact(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "inexact")?;
+    Ok(Expression::NumberLiteral(
+        expect_number(&args[0])?.to_inexact(),
+    ))
+}
+
+fn builtin_numerator(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "numerator")?;
+    match expect_number(&args[0])?.numerator() {
+        Some(n) => Ok(Expression::NumberLiteral(n)),
+        None => Err(SchemeError::wrong_type("rational number", &args[0])),
+    }
+}
+
+fn builtin_denominator(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "denominator")?;
+    match expect_number(&args[0])?.denominator() {
+        Some(n) => Ok(Expression::NumberLiteral(n)),
+        None => Err(SchemeError::wrong_type("rational number", &args[0])),
+    }
+}
+
+fn builtin_make_rectangular(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "make-rectangular")?;
+    Ok(Expression::NumberLiteral(Number::rectangular(
+        expect_real(&args[0])?,
+        expect_real(&args[1])?,
+    )))
+}
+
+fn builtin_make_polar(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "make-polar")?;
+    Ok(Expression::NumberLiteral(Number::polar(
+        expect_real(&args[0])?,
+        expect_real(&args[1])?,
+    )))
+}
+
+fn builtin_real_part(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "real-part")?;
+    Ok(Expression::NumberLiteral(
+        expect_number(&args[0])?.real_part(),
+    ))
+}
+
+fn builtin_imag_part(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "imag-part")?;
+    Ok(Expression::NumberLiteral(
+        expect_number(&args[0])?.imag_part(),
+    ))
+}
+
+fn builtin_magnitude(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "magnitude")?;
+    Ok(Expression::NumberLiteral(
+        expect_number(&args[0])?.magnitude(),
+    ))
+}
+
+fn builtin_angle(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "angle")?;
+    Ok(Expression::NumberLiteral(expect_number(&args[0])?.angle()))
+}
+
+fn builtin_sqrt(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "sqrt")?;
+    Ok(Expression::NumberLiteral(expect_number(&args[0])?.sqrt()))
+}
+
+fn expect_integer(value: &Expression) -> Result<Number, SchemeError> {
+    match value {
+        Expression::NumberLiteral(n) if n.is_integer() => Ok(n.clone()),
+        _ => Err(SchemeError::wrong_type("integer", value)),
+    }
+}
+
+fn number_predicate(
+    args: &[Expression],
+    name: &str,
+    predicate: fn(&Number) -> bool,
+) -> Result<Expression, SchemeError> {
+    check_arity(args, 1, name)?;
+    Ok(Expression::BooleanLiteral(
+        matches!(&args[0], Expression::NumberLiteral(n) if predicate(n)),
+    ))
+}
+
+fn builtin_is_number(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    number_predicate(&args, "number?", |_| true)
+}
+
+fn builtin_is_real(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    number_predicate(&args, "real?", Number::is_real)
+}
+
+fn builtin_is_rational(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    number_predicate(&args, "rational?", Number::is_rational)
+}
+
+fn builtin_is_integer(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    number_predicate(&args, "integer?", Number::is_integer)
+}
+
+fn builtin_is_exact_integer(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    number_predicate(&args, "exact-integer?", |n| n.is_exact() && n.is_integer())
+}
+
+fn builtin_is_nan(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "nan?")?;
+    Ok(Expression::BooleanLiteral(
+        expect_number(&args[0])?.is_nan(),
+    ))
+}
+
+fn builtin_is_infinite(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "infinite?")?;
+    Ok(Expression::BooleanLiteral(
+        expect_number(&args[0])?.is_infinite(),
+    ))
+}
+
+fn builtin_is_finite(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "finite?")?;
+    let n = expect_number(&args[0])?;
+    Ok(Expression::BooleanLiteral(!n.is_nan() && !n.is_infinite()))
+}
+
+fn builtin_is_zero(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "zero?")?;
+    Ok(Expression::BooleanLiteral(
+        expect_number(&args[0])?.is_zero(),
+    ))
+}
+
+fn builtin_is_positive(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "positive?")?;
+    Ok(Expression::BooleanLiteral(
+        expect_real(&args[0])?.is_positive(),
+    ))
+}
+
+fn builtin_is_negative(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "negative?")?;
+    Ok(Expression::BooleanLiteral(
+        expect_real(&args[0])?.is_negative(),
+    ))
+}
+
+fn builtin_is_odd(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "odd?")?;
+    Ok(Expression::BooleanLiteral(!Number::is_even(
+        &expect_integer(&args[0])?,
+    )))
+}
+
+fn builtin_is_even(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "even?")?;
+    Ok(Expression::BooleanLiteral(Number::is_even(
+        &expect_integer(&args[0])?,
+    )))
+}
+
+/// Applies an integer division that returns the quotient and the remainder
+/// to the two arguments.
+fn integer_division(
+    args: &[Expression],
+    name: &str,
+    divide: fn(&Number, &Number) -> Option<(Number, Number)>,
+) -> Result<(Number, Number), SchemeError> {
+    check_arity(args, 2, name)?;
+    let (dividend, divisor) = (expect_integer(&args[0])?, expect_integer(&args[1])?);
+    divide(&dividend, &divisor).ok_or_else(|| ErrorKind::DivisionByZero.into())
+}
+
+fn builtin_quotient(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let (quotient, _) = integer_division(&args, "quotient", Number::truncate_div)?;
+    Ok(Expression::NumberLiteral(quotient))
+}
+
+fn builtin_remainder(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let (_, remainder) = integer_division(&args, "remainder", Number::truncate_div)?;
+    Ok(Expression::NumberLiteral(remainder))
+}
+
+fn builtin_floor_quotient(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let (quotient, _) = integer_division(&args, "floor-quotient", Number::floor_div)?;
+    Ok(Expression::NumberLiteral(quotient))
+}
+
+fn builtin_modulo(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let (_, modulo) = integer_division(&args, "modulo", Number::floor_div)?;
+    Ok(Expression::NumberLiteral(modulo))
+}
+
+// There are no multiple values, so the division procedures returning both
+// the quotient and the remainder return them as a two-element list.
+
+fn builtin_floor_div(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let (quotient, modulo) = integer_division(&args, "floor/", Number::floor_div)?;
+    Ok(Expression::list(vec![
+        Expression::NumberLiteral(quotient),
+        Expression::NumberLiteral(modulo),
+    ]))
+}
+
+fn builtin_truncate_div(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let (quotient, remainder) = integer_division(&args, "truncate/", Number::truncate_div)?;
+    Ok(Expression::list(vec![
+        Expression::NumberLiteral(quotient),
+        Expression::NumberLiteral(remainder),
+    ]))
+}
+
+fn builtin_gcd(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    Ok(Expression::NumberLiteral(
+        args.iter().try_fold(Number::from(0), |acc, v| {
+            Ok::<_, SchemeError>(acc.gcd(&expect_integer(v)?))
+        })?,
+    ))
+}
+
+fn builtin_lcm(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    Ok(Expression::NumberLiteral(
+        args.iter().try_fold(Number::from(1), |acc, v| {
+            Ok::<_, SchemeError>(acc.lcm(&expect_integer(v)?))
+        })?,
+    ))
+}
+
+fn builtin_abs(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "abs")?;
+    Ok(Expression::NumberLiteral(
+        expect_real(&args[0])?.magnitude(),
+    ))
+}
+
+/// Picks the argument that `pick` prefers over all the others. The result is
+/// inexact if any argument is.
+fn extremum(
+    args: &[Expression],
+    name: &str,
+    pick: fn(&Number, &Number) -> bool,
+) -> Result<Expression, SchemeError> {
+    check_arity_range(args, 1, None, name)?;
+    let nums = extract_reals(args.to_vec())?;
+    let mut result = nums[0].clone();
+    for n in &nums[1..] {
+        if pick(n, &result) {
+            result = n.clone();
+        }
+    }
+    if nums.iter().any(|n| !n.is_exact()) {
+        result = result.to_inexact();
+    }
+    Ok(Expression::NumberLiteral(result))
+}
+
+fn builtin_min(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    extremum(&args, "min", |a, b| a < b)
+}
+
+fn builtin_max(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    extremum(&args, "max", |a, b| a > b)
+}
+
+fn builtin_floor(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "floor")?;
+    Ok(Expression::NumberLiteral(expect_real(&args[0])?.floor()))
+}
+
+fn builtin_ceiling(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "ceiling")?;
+    Ok(Expression::NumberLiteral(expect_real(&args[0])?.ceiling()))
+}
+
+fn builtin_round(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "round")?;
+    Ok(Expression::NumberLiteral(expect_real(&args[0])?.round()))
+}
+
+fn builtin_truncate(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "truncate")?;
+    Ok(Expression::NumberLiteral(expect_real(&args[0])?.truncate()))
+}
+
+fn builtin_exact_integer_sqrt(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "exact-integer-sqrt")?;
+    match expect_number(&args[0])?.exact_integer_sqrt() {
+        Some((root, remainder)) => Ok(Expression::list(vec![
+            Expression::NumberLiteral(root),
+            Expression::NumberLiteral(remainder),
+        ])),
+        None => Err(SchemeError::wrong_type(
+            "non-negative exact integer",
+            &args[0],
+        )),
+    }
+}
+
+fn builtin_square(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "square")?;
+    let n = expect_number(&args[0])?;
+    Ok(Expression::NumberLiteral(n.clone() * n))
+}
+
+fn builtin_expt(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "expt")?;
+    let (base, exponent) = (expect_number(&args[0])?, expect_number(&args[1])?);
+    Ok(Expression::NumberLiteral(base.expt(&exponent)?))
+}
+
+fn builtin_exp(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "exp")?;
+    Ok(Expression::NumberLiteral(expect_number(&args[0])?.exp()))
+}
+
+fn builtin_log(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, Some(2), "log")?;
+    let log = expect_number(&args[0])?.log();
+    match args.get(1) {
+        Some(base) => match log.checked_div(&expect_number(base)?.log()) {
+            Some(n) => Ok(Expression::NumberLiteral(n)),
+            None => Err(ErrorKind::DivisionByZero.into()),
+        },
+        None => Ok(Expression::NumberLiteral(log)),
+    }
+}
+
+/// Applies a floating-point function to a real argument.
+fn float_function(
+    args: &[Expression],
+    name: &str,
+    function: fn(f64) -> f64,
+) -> Result<Expression, SchemeError> {
+    check_arity(args, 1, name)?;
+    let x = expect_real(&args[0])?.to_inexact();
+    match x {
+        Number::Float(x) => Ok(Expression::NumberLiteral(Number::Float(function(x)))),
+        _ => unreachable!("inexact reals are floats"),
+    }
+}
+
+fn builtin_sin(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    float_function(&args, "sin", f64::sin)
+}
+
+fn builtin_cos(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    float_function(&args, "cos", f64::cos)
+}
+
+fn builtin_tan(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    float_function(&args, "tan", f64::tan)
+}
+
+fn builtin_asin(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    float_function(&args, "asin", f64::asin)
+}
+
+fn builtin_acos(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    float_function(&args, "acos", f64::acos)
+}
+
+fn builtin_atan(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, Some(2), "atan")?;
+    match args.get(1) {
+        Some(x) => {
+            let (y, x) = (expect_real(&args[0])?, expect_real(x)?);
+            Ok(Expression::NumberLiteral(
+                Number::rectangular(x, y).angle().to_inexact(),
+            ))
+        }
+        None => float_function(&args, "atan", f64::atan),
+    }
+}
+
+fn expect_radix(value: &Expression) -> Result<u32, SchemeError> {
+    match value {
+        Expression::NumberLiteral(Number::Int(radix)) if matches!(radix, 2 | 8 | 10 | 16) => {
+            Ok(*radix as u32)
+        }
+        _ => Err(SchemeError::wrong_type("radix 2, 8, 10 or 16", value)),
+    }
+}
+
+fn builtin_number_to_string(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, Some(2), "number->string")?;
+    let radix = args.get(1).map_or(Ok(10), expect_radix)?;
+    match expect_number(&args[0])?.to_string_radix(radix) {
+        Some(s) => Ok(Expression::string(s)),
+        None => Err(SchemeError::wrong_type("exact number", &args[0])),
+    }
+}
+
+fn builtin_string_to_number(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, Some(2), "string->number")?;
+    let radix = args.get(1).map_or(Ok(10), expect_radix)?;
+    let s = expect_string(&args[0])?.borrow();
+    match Number::from_str_radix(&s, radix) {
+        Ok(n) => Ok(Expression::NumberLiteral(n)),
+        Err(_) => Ok(Expression::BooleanLiteral(false)),
+    }
+}
+
+fn check_arity_range(
+    args: &[Expression],
+    min: usize,
+    max: Option<usize>,
+    name: &str,
+) -> Result<(), SchemeError> {
+    if args.len() >= min && max.is_none_or(|max| args.len() <= max) {
+        Ok(())
+    } else {
+        Err(ErrorKind::Arity {
+            procedure: name.to_string(),
+            min,
+            max,
+            actual: args.len(),
+        }
+        .into())
+    }
+}
+
+fn check_arity(args: &[Expression], count: usize, name: &str) -> Result<(), SchemeError> {
+    check_arity_range(args, count, Some(count), name)
+}
+
+fn expect_pair(value: &Expression) -> Result<&Rc<RefCell<Pair>>, SchemeError> {
+    match value {
+        Expression::Pair(pair) => Ok(pair),
+        _ => Err(SchemeError::wrong_type("pair", value)),
+    }
+}
+
+fn expect_list(value: &Expression) -> Result<Vec<Expression>, SchemeError> {
+    value
+        .list_to_vec()
+        .ok_or_else(|| SchemeError::wrong_type("list", value))
+}
+
+fn builtin_cons(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "cons")?;
+    let mut args = args.into_iter();
+    let car = args.next().unwrap();
+    let cdr = args.next().unwrap();
+    Ok(Expression::cons(car, cdr))
+}
+
+fn builtin_car(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "car")?;
+    Ok(expect_pair(&args[0])?.borrow().car.clone())
+}
+
+fn builtin_cdr(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "cdr")?;
+    Ok(expect_pair(&args[0])?.borrow().cdr.clone())
+}
+
+fn builtin_set_car(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "set-car!")?;
+    expect_pair(&args[0])?.borrow_mut().car = args[1].clone();
+    Ok(Expression::Void)
+}
+
+fn builtin_set_cdr(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "set-cdr!")?;
+    expect_pair(&args[0])?.borrow_mut().cdr = args[1].clone();
+    Ok(Expression::Void)
+}
+
+fn builtin_list(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    Ok(Expression::list(args))
+}
+
+fn builtin_is_null(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "null?")?;
+    Ok(Expression::BooleanLiteral(matches!(
+        args[0],
+        Expression::EmptyList
+    )))
+}
+
+fn builtin_is_pair(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "pair?")?;
+    Ok(Expression::BooleanLiteral(matches!(
+        args[0],
+        Expression::Pair(_)
+    )))
+}
+
+fn builtin_is_list(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "list?")?;
+    Ok(Expression::BooleanLiteral(args[0].list_to_vec().is_some()))
+}
+
+fn builtin_length(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "length")?;
+    let elements = expect_list(&args[0])?;
+    Ok(Expression::NumberLiteral(Number::from(
+        elements.len() as i64
+    )))
+}
+
+fn builtin_append(mut args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let mut result = args.pop().unwrap_or(Expression::EmptyList);
+    for list in args.iter().rev() {
+        let elements = expect_list(list)?;
+        result = elements
+            .into_iter()
+            .rev()
+            .fold(result, |tail, e| Expression::cons(e, tail));
+    }
+    Ok(result)
+}
+
+fn builtin_reverse(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "reverse")?;
+    let elements = expect_list(&args[0])?;
+    Ok(elements
+        .into_iter()
+        .fold(Expression::EmptyList, |tail, e| Expression::cons(e, tail)))
+}
+
+fn expect_string(value: &Expression) -> Result<&Rc<RefCell<String>>, SchemeError> {
+    match value {
+        Expression::StringLiteral(s) => Ok(s),
+        _ => Err(SchemeError::wrong_type("string", value)),
+    }
+}
+
+fn builtin_is_symbol(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "symbol?")?;
+    Ok(Expression::BooleanLiteral(matches!(
+        args[0],
+        Expression::Symbol(_)
+    )))
+}
+
+fn builtin_symbol_to_string(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "symbol->string")?;
+    match &args[0] {
+        Expression::Symbol(s) => Ok(Expression::string(s.as_str())),
+        other => Err(SchemeError::wrong_type("symbol", other)),
+    }
+}
+
+fn builtin_string_to_symbol(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "string->symbol")?;
+    Ok(Expression::Symbol(Symbol::new(
+        &expect_string(&args[0])?.borrow(),
+    )))
+}
+
+fn builtin_string_to_uninterned_symbol(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "string->uninterned-symbol")?;
+    Ok(Expression::Symbol(Symbol::uninterned(
+        &expect_string(&args[0])?.borrow(),
+    )))
+}
+
+fn builtin_gensym(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 0, Some(1), "gensym")?;
+    let prefix = match args.first() {
+        Some(prefix) => expect_string(prefix)?.borrow().clone(),
+        None => "g".to_string(),
+    };
+    Ok(Expression::Symbol(Symbol::gensym(&prefix)))
+}
+
+fn builtin_is_string(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "string?")?;
+    Ok(Expression::BooleanLiteral(matches!(
+        args[0],
+        Expression::StringLiteral(_)
+    )))
+}
+
+/// Checks that `value` is a position between 0 and `max`, inclusive, as used
+/// for the bounds of a substring.
+fn expect_position(value: &Expression, max: usize) -> Result<usize, SchemeError> {
+    match value {
+        Expression::NumberLiteral(Number::Int(n)) if (0..=max as i64).contains(n) => {
+            Ok(*n as usize)
+        }
+        _ => Err(SchemeError::wrong_type("valid index", value)),
+    }
+}
+
+/// Returns the characters of the string in `args[0]` between the optional
+/// start and end positions in `args[1]` and `args[2]`.
+fn string_range(args: &[Expression]) -> Result<Vec<char>, SchemeError> {
+    let chars: Vec<char> = expect_string(&args[0])?.borrow().chars().collect();
+    let end = match args.get(2) {
+        Some(end) => expect_position(end, chars.len())?,
+        None => chars.len(),
+    };
+    let start = match args.get(1) {
+        Some(start) => expect_position(start, end)?,
+        None => 0,
+    };
+    Ok(chars[start..end].to_vec())
+}
+
+/// The longest string `make-string` creates, which keeps a typo in the length
+/// from exhausting memory.
+const MAX_STRING_LENGTH: usize = 1 << 28;
+
+fn builtin_make_string(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, Some(2), "make-string")?;
+    let len = expect_position(&args[0], usize::MAX >> 1)?;
+    let fill = match args.get(1) {
+        Some(fill) => expect_char(fill)?,
+        None => ' ',
+    };
+    let too_long = || {
+        let message = format!("cannot make a string of length {}", len);
+        SchemeError::from(ErrorKind::ImplementationRestriction(message))
+    };
+    if len > MAX_STRING_LENGTH {
+        return Err(too_long());
+    }
+    let mut s = String::new();
+    s.try_reserve_exact(len * fill.len_utf8())
+        .map_err(|_| too_long())?;
+    s.extend(std::iter::repeat_n(fill, len));
+    Ok(Expression::string(s))
+}
+
+fn builtin_string(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let s = args
+        .iter()
+        .map(expect_char)
+        .collect::<Result<String, _>>()?;
+    Ok(Expression::string(s))
+}
+
+fn builtin_string_length(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "string-length")?;
+    let len = expect_string(&args[0])?.borrow().chars().count();
+    Ok(Expression::NumberLiteral(Number::Int(len as i64)))
+}
+
+fn builtin_string_ref(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "string-ref")?;
+    let s = expect_string(&args[0])?.borrow();
+    let index = expect_index(&args[1], s.chars().count())?;
+    Ok(Expression::CharLiteral(s.chars().nth(index).unwrap()))
+}
+
+fn builtin_string_set(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 3, "string-set!")?;
+    let mut s = expect_string(&args[0])?.borrow_mut();
+    let index = expect_index(&args[1], s.chars().count())?;
+    let c = expect_char(&args[2])?;
+    let (offset, old) = s.char_indices().nth(index).unwrap();
+    s.replace_range(offset..offset + old.len_utf8(), c.encode_utf8(&mut [0; 4]));
+    Ok(Expression::Void)
+}
+
+fn builtin_substring(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 2, Some(3), "substring")?;
+    Ok(Expression::string(
+        string_range(&args)?.into_iter().collect::<String>(),
+    ))
+}
+
+fn builtin_string_append(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let mut result = String::new();
+    for arg in &args {
+        result.push_str(&expect_string(arg)?.borrow());
+    }
+    Ok(Expression::string(result))
+}
+
+fn builtin_string_copy(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, Some(3), "string-copy")?;
+    Ok(Expression::string(
+        string_range(&args)?.into_iter().collect::<String>(),
+    ))
+}
+
+fn builtin_string_to_list(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, Some(3), "string->list")?;
+    Ok(Expression::list(
+        string_range(&args)?
+            .into_iter()
+            .map(Expression::CharLiteral),
+    ))
+}
+
+fn builtin_list_to_string(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "list->string")?;
+    let chars = expect_list(&args[0])?;
+    let s = chars
+        .iter()
+        .map(expect_char)
+        .collect::<Result<String, _>>()?;
+    Ok(Expression::string(s))
+}
+
+fn builtin_string_upcase(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "string-upcase")?;
+    Ok(Expression::string(
+        expect_string(&args[0])?.borrow().to_uppercase(),
+    ))
+}
+
+fn builtin_string_downcase(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "string-downcase")?;
+    Ok(Expression::string(
+        expect_string(&args[0])?.borrow().to_lowercase(),
+    ))
+}
+
+/// Checks that each pair of adjacent strings in `args` compares as accepted
+/// by `test`, optionally ignoring case.
+fn compare_strings(
+    args: &[Expression],
+    name: &str,
+    fold_case: bool,
+    test: fn(Ordering) -> bool,
+) -> Result<Expression, SchemeError> {
+    check_arity_range(args, 1, None, name)?;
+    let strings = args
+        .iter()
+        .map(|arg| {
+            let s = expect_string(arg)?.borrow();
+            Ok(if fold_case {
+                s.to_lowercase()
+            } else {
+                s.clone()
+            })
+        })
+        .collect::<Result<Vec<_>, SchemeError>>()?;
+    let result = strings.windows(2).all(|pair| test(pair[0].cmp(&pair[1])));
+    Ok(Expression::BooleanLiteral(result))
+}
+
+fn builtin_string_eq(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string=?", false, Ordering::is_eq)
+}
+
+fn builtin_string_lt(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string<?", false, Ordering::is_lt)
+}
+
+fn builtin_string_gt(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string>?", false, Ordering::is_gt)
+}
+
+fn builtin_string_le(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string<=?", false, Ordering::is_le)
+}
+
+fn builtin_string_ge(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string>=?", false, Ordering::is_ge)
+}
+
+fn builtin_string_ci_eq(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string-ci=?", true, Ordering::is_eq)
+}
+
+fn builtin_string_ci_lt(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string-ci<?", true, Ordering::is_lt)
+}
+
+fn builtin_string_ci_gt(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string-ci>?", true, Ordering::is_gt)
+}
+
+fn builtin_string_ci_le(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string-ci<=?", true, Ordering::is_le)
+}
+
+fn builtin_string_ci_ge(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    compare_strings(&args, "string-ci>=?", true, Ordering::is_ge)
+}
+
+/// `(string-split string [separator])` splits at each occurrence of a
+/// separator character or string, or at runs of whitespace if none is given.
+fn builtin_string_split(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, Some(2), "string-split")?;
+    let s = expect_string(&args[0])?.borrow();
+    let parts: Vec<&str> = match args.get(1) {
+        None => s.split_whitespace().collect(),
+        Some(Expression::CharLiteral(c)) => s.split(*c).collect(),
+        Some(Expression::StringLiteral(separator)) if !separator.borrow().is_empty() => {
+            s.split(separator.borrow().as_str()).collect()
+        }
+        Some(other) => return Err(SchemeError::wrong_type("separator", other)),
+    };
+    Ok(Expression::list(parts.into_iter().map(Expression::string)))
+}
+
+/// `(string-join list [delimiter])` concatenates a list of strings, separated
+/// by a space unless another delimiter is given.
+fn builtin_string_join(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, Some(2), "string-join")?;
+    let strings = expect_list(&args[0])?
+        .iter()
+        .map(|s| Ok(expect_string(s)?.borrow().clone()))
+        .collect::<Result<Vec<_>, SchemeError>>()?;
+    let delimiter = match args.get(1) {
+        Some(delimiter) => expect_string(delimiter)?.borrow().clone(),
+        None => " ".to_string(),
+    };
+    Ok(Expression::string(strings.join(&delimiter)))
+}
+
+/// `(string-index string pred [start [end]])` returns the index of the first
+/// character matching `pred`, which is a character or a predicate procedure,
+/// or `#f` if there is none.
+fn builtin_string_index(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 2, Some(4), "string-index")?;
+    let mut range_args = vec![args[0].clone()];
+    range_args.extend(args[2..].iter().cloned());
+    let chars = string_range(&range_args)?;
+    let start = match args.get(2) {
+        Some(start) => expect_position(start, usize::MAX >> 1)?,
+        None => 0,
+    };
+    for (i, c) in chars.into_iter().enumerate() {
+        let found = match &args[1] {
+            Expression::CharLiteral(target) => c == *target,
+            pred @ (Expression::Procedure(_) | Expression::BuiltinProcedure(_)) => {
+                is_true(&apply(pred.clone(), vec![Expression::CharLiteral(c)])?)
+            }
+            other => return Err(SchemeError::wrong_type("character or procedure", other)),
+        };
+        if found {
+            return Ok(Expression::NumberLiteral(Number::Int((start + i) as i64)));
+        }
+    }
+    Ok(Expression::BooleanLiteral(false))
+}
+
+fn builtin_is_boolean(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "boolean?")?;
+    Ok(Expression::BooleanLiteral(matches!(
+        args[0],
+        Expression::BooleanLiteral(_)
+    )))
+}
+
+fn builtin_is_char(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "char?")?;
+    Ok(Expression::BooleanLiteral(matches!(
+        args[0],
+        Expression::CharLiteral(_)
+    )))
+}
+
+fn expect_char(value: &Expression) -> Result<char, SchemeError> {
+    match value {
+        Expression::CharLiteral(c) => Ok(*c),
+        _ => Err(SchemeError::wrong_type("character", value)),
+    }
+}
+
+fn builtin_char_to_integer(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "char->integer")?;
+    let c = expect_char(&args[0])?;
+    Ok(Expression::NumberLiteral(Number::Int(c as i64)))
+}
+
+fn builtin_integer_to_char(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "integer->char")?;
+    match &args[0] {
+        Expression::NumberLiteral(Number::Int(n)) => u32::try_from(*n)
+            .ok()
+            .and_then(char::from_u32)
+            .map(Expression::CharLiteral)
+            .ok_or_else(|| SchemeError::wrong_type("Unicode scalar value", &args[0])),
+        other => Err(SchemeError::wrong_type("integer", other)),
+    }
+}
+
+/// Checks that `value` is a valid index into a sequence of length `len`.
+fn expect_index(value: &Expression, len: usize) -> Result<usize, SchemeError> {
+    match value {
+        Expression::NumberLiteral(Number::Int(n)) if (0..len as i64).contains(n) => Ok(*n as usize),
+        _ => Err(SchemeError::wrong_type("valid index", value)),
+    }
+}
+
+fn builtin_is_vector(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "vector?")?;
+    Ok(Expression::BooleanLiteral(matches!(
+        args[0],
+        Expression::Vector(_)
+    )))
+}
+
+fn expect_vector(value: &Expression) -> Result<&Rc<RefCell<Vec<Expression>>>, SchemeError> {
+    match value {
+        Expression::Vector(elements) => Ok(elements),
+        _ => Err(SchemeError::wrong_type("vector", value)),
+    }
+}
+
+fn builtin_vector_length(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "vector-length")?;
+    let len = expect_vector(&args[0])?.borrow().len();
+    Ok(Expression::NumberLiteral(Number::Int(len as i64)))
+}
+
+fn builtin_vector_ref(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "vector-ref")?;
+    let elements = expect_vector(&args[0])?.borrow();
+    let index = expect_index(&args[1], elements.len())?;
+    Ok(elements[index].clone())
+}
+
+fn builtin_is_bytevector(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "bytevector?")?;
+    Ok(Expression::BooleanLiteral(matches!(
+        args[0],
+        Expression::Bytevector(_)
+    )))
+}
+
+fn expect_bytevector(value: &Expression) -> Result<&Rc<RefCell<Vec<u8>>>, SchemeError> {
+    match value {
+        Expression::Bytevector(bytes) => Ok(bytes),
+        _ => Err(SchemeError::wrong_type("bytevector", value)),
+    }
+}
+
+fn builtin_bytevector_length(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 1, "bytevector-length")?;
+    let len = expect_bytevector(&args[0])?.borrow().len();
+    Ok(Expression::NumberLiteral(Number::Int(len as i64)))
+}
+
+fn builtin_bytevector_u8_ref(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "bytevector-u8-ref")?;
+    let bytes = expect_bytevector(&args[0])?.borrow();
+    let index = expect_index(&args[1], bytes.len())?;
+    Ok(Expression::NumberLiteral(Number::Int(bytes[index] as i64)))
+}
+
+fn builtin_is_eq(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "eq?")?;
+    Ok(Expression::BooleanLiteral(args[0].is_eq(&args[1])))
+}
+
+fn builtin_is_eqv(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "eqv?")?;
+    Ok(Expression::BooleanLiteral(args[0].is_eqv(&args[1])))
+}
+
+fn builtin_is_equal(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity(&args, 2, "equal?")?;
+    Ok(Expression::BooleanLiteral(args[0].is_equal(&args[1])))
+}
+
+fn builtin_error(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 1, None, "error")?;
+    let mut args = args.into_iter();
+    let message = match args.next().unwrap() {
+        Expression::StringLiteral(message) => message.borrow().clone(),
+        other => other.to_string(),
+    };
+    Err(ErrorKind::User {
+        message,
+        irritants: args.collect(),
+    }
+    .into())
+}
+
+fn builtin_exit(args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    check_arity_range(&args, 0, Some(1), "exit")?;
+    let status = match args.first() {
+        None | Some(Expression::BooleanLiteral(true)) => 0,
+        Some(Expression::BooleanLiteral(false)) => 1,
+        // Only the low byte of a status reaches the parent process, so reject
+        // anything that would be silently truncated.
+        Some(Expression::NumberLiteral(Number::Int(n))) if (0..=255).contains(n) => *n as i32,
+        Some(other) => return Err(SchemeError::wrong_type("exit status", other)),
+    };
+    Err(ErrorKind::Exit(status).into())
+}
+
+type Builtin = fn(Vec<Expression>) -> Result<Expression, SchemeError>;
+
+const BUILTINS: &[(&str, Builtin)] = &[
+    ("+", builtin_add),
+    ("-", builtin_sub),
+    ("*", builtin_mul),
+    ("/", builtin_div),
+    (">", builtin_greater_than),
+    ("<", builtin_less_than),
+    ("=", builtin_equal),
+    (">=", builtin_greater_or_equal),
+    ("<=", builtin_less_or_equal),
+    ("exact?", builtin_is_exact),
+    ("inexact?", builtin_is_inexact),
+    ("exact", builtin_exact),
+    ("inexact", builtin_inexact),
+    ("inexact->exact", builtin_exact),
+    ("exact->inexact", builtin_inexact),
+    ("numerator", builtin_numerator),
+    ("denominator", builtin_denominator),
+    ("make-rectangular", builtin_make_rectangular),
+    ("make-polar", builtin_make_polar),
+    ("real-part", builtin_real_part),
+    ("imag-part", builtin_imag_part),
+    ("magnitude", builtin_magnitude),
+    ("angle", builtin_angle),
+    ("sqrt", builtin_sqrt),
+    ("number?", builtin_is_number),
+    ("complex?", builtin_is_number),
+    ("real?", builtin_is_real),
+    ("rational?", builtin_is_rational),
+    ("integer?", builtin_is_integer),
+    ("exact-integer?", builtin_is_exact_integer),
+    ("nan?", builtin_is_nan),
+    ("infinite?", builtin_is_infinite),
+    ("finite?", builtin_is_finite),
+    ("zero?", builtin_is_zero),
+    ("positive?", builtin_is_positive),
+    ("negative?", builtin_is_negative),
+    ("odd?", builtin_is_odd),
+    ("even?", builtin_is_even),
+    ("quotient", builtin_quotient),
+    ("remainder", builtin_remainder),
+    ("modulo", builtin_modulo),
+    ("floor/", builtin_floor_div),
+    ("floor-quotient", builtin_floor_quotient),
+    ("floor-remainder", builtin_modulo),
+    ("truncate/", builtin_truncate_div),
+    ("truncate-quotient", builtin_quotient),
+    ("truncate-remainder", builtin_remainder),
+    ("gcd", builtin_gcd),
+    ("lcm", builtin_lcm),
+    ("abs", builtin_abs),
+    ("min", builtin_min),
+    ("max", builtin_max),
+    ("floor", builtin_floor),
+    ("ceiling", builtin_ceiling),
+    ("round", builtin_round),
+    ("truncate", builtin_truncate),
+    ("exact-integer-sqrt", builtin_exact_integer_sqrt),
+    ("square", builtin_square),
+    ("expt", builtin_expt),
+    ("exp", builtin_exp),
+    ("log", builtin_log),
+    ("sin", builtin_sin),
+    ("cos", builtin_cos),
+    ("tan", builtin_tan),
+    ("asin", builtin_asin),
+    ("acos", builtin_acos),
+    ("atan", builtin_atan),
+    ("number->string", builtin_number_to_string),
+    ("string->number", builtin_string_to_number),
+    ("eq?", builtin_is_eq),
+    ("eqv?", builtin_is_eqv),
+    ("equal?", builtin_is_equal),
+    ("cons", builtin_cons),
+    ("car", builtin_car),
+    ("cdr", builtin_cdr),
+    ("set-car!", builtin_set_car),
+    ("set-cdr!", builtin_set_cdr),
+    ("list", builtin_list),
+    ("null?", builtin_is_null),
+    ("pair?", builtin_is_pair),
+    ("list?", builtin_is_list),
+    ("length", builtin_length),
+    ("append", builtin_append),
+    ("reverse", builtin_reverse),
+    ("symbol?", builtin_is_symbol),
+    ("symbol->string", builtin_symbol_to_string),
+    ("string->symbol", builtin_string_to_symbol),
+    (
+        "string->uninterned-symbol",
+        builtin_string_to_uninterned_symbol,
+    ),
+    ("gensym", builtin_gensym),
+    ("string?", builtin_is_string),
+    ("make-string", builtin_make_string),
+    ("string", builtin_string),
+    ("string-length", builtin_string_length),
+    ("string-ref", builtin_string_ref),
+    ("string-set!", builtin_string_set),
+    ("substring", builtin_substring),
+    ("string-append", builtin_string_append),
+    ("string-copy", builtin_string_copy),
+    ("string->list", builtin_string_to_list),
+    ("list->string", builtin_list_to_string),
+    ("string-upcase", builtin_string_upcase),
+    ("string-downcase", builtin_string_downcase),
+    ("string=?", builtin_string_eq),
+    ("string<?", builtin_string_lt),
+    ("string>?", builtin_string_gt),
+    ("string<=?", builtin_string_le),
+    ("string>=?", builtin_string_ge),
+    ("string-ci=?", builtin_string_ci_eq),
+    ("string-ci<?", builtin_string_ci_lt),
+    ("string-ci>?", builtin_string_ci_gt),
+    ("string-ci<=?", builtin_string_ci_le),
+    ("string-ci>=?", builtin_string_ci_ge),
+    ("string-split", builtin_string_split),
+    ("string-join", builtin_string_join),
+    ("string-index", builtin_string_index),
+    ("boolean?", builtin_is_boolean),
+    ("char?", builtin_is_char),
+    ("char->integer", builtin_char_to_integer),
+    ("integer->char", builtin_integer_to_char),
+    ("vector?", builtin_is_vector),
+    ("vector-length", builtin_vector_length),
+    ("vector-ref", builtin_vector_ref),
+    ("bytevector?", builtin_is_bytevector),
+    ("bytevector-length", builtin_bytevector_length),
+    ("bytevector-u8-ref", builtin_bytevector_u8_ref),
+    ("error", builtin_error),
+    ("exit", builtin_exit),
+];
+
+/// Binds `command-line` in `env` to a procedure returning `arguments` as a
+/// list of strings, the first being the name of the program or script.
+pub fn define_command_line(env: &Environment, arguments: Vec<String>) {
+    let arguments = Expression::list(arguments.into_iter().map(Expression::string));
+    env.define(
+        Symbol::new("command-line"),
+        Expression::BuiltinProcedure(Rc::new(move |args: Vec<Expression>| {
+            check_arity(&args, 0, "command-line")?;
+            Ok(arguments.clone())
+        })),
     );
+}
+
+pub fn create_root_environment() -> Environment {
+    let root_env = Environment::new();
+
+    for (name, builtin) in BUILTINS {
+        root_env.define(
+            Symbol::new(name),
+            Expression::BuiltinProcedure(Rc::new(*builtin)),
+        );
+    }
+    define_command_line(&root_env, Vec::new());
 
     root_env
 }
+
+#[cfg(test)]
+mod test {
+    use super::Environment;
+    use crate::expression::Expression;
+    use crate::symbol::Symbol;
+
+    #[test]
+    fn frames_walk_parent_chain() {
+        let root = Environment::new();
+        root.define(Symbol::new("x"), Expression::BooleanLiteral(true));
+        let child = root.extend();
+        assert_eq!(
+            child.lookup(&Symbol::new("x")),
+            Some(Expression::BooleanLiteral(true))
+        );
+
+        child
+            .set(&Symbol::new("x"), Expression::BooleanLiteral(false))
+            .unwrap();
+        assert_eq!(
+            root.lookup(&Symbol::new("x")),
+            Some(Expression::BooleanLiteral(false))
+        );
+
+        child.define(Symbol::new("x"), Expression::Void);
+        assert_eq!(child.lookup(&Symbol::new("x")), Some(Expression::Void));
+        assert_eq!(
+            root.lookup(&Symbol::new("x")),
+            Some(Expression::BooleanLiteral(false))
+        );
+
+        assert!(child.set(&Symbol::new("y"), Expression::Void).is_err());
+        assert_eq!(child.lookup(&Symbol::new("y")), None);
+    }
+}
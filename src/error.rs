@@ -0,0 +1,225 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::expression::Expression;
+use crate::span::{SourceId, Span};
+
+/// The number of innermost calls shown in a backtrace when reporting an error.
+const MAX_REPORTED_FRAMES: usize = 20;
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// Malformed source text or special form.
+    Syntax(String),
+    /// Source text that ends in the middle of a datum, which more input
+    /// could complete.
+    Incomplete(String),
+    UnboundVariable(String),
+    WrongType {
+        expected: &'static str,
+        actual: Expression,
+    },
+    /// A call with `actual` arguments to a procedure accepting between `min`
+    /// and `max` (if bounded) arguments.
+    Arity {
+        procedure: String,
+        min: usize,
+        max: Option<usize>,
+        actual: usize,
+    },
+    DivisionByZero,
+    /// A valid operation whose result is larger than the interpreter supports.
+    ImplementationRestriction(String),
+    /// Raised by the `error` procedure.
+    User {
+        message: String,
+        irritants: Vec<Expression>,
+    },
+    Io(io::Error),
+    /// Raised by the `exit` procedure to end the program with a status code.
+    Exit(i32),
+}
+
+/// A procedure call that was in progress when an error occurred.
+#[derive(Debug)]
+pub struct Frame {
+    /// The name of the procedure, if it has one.
+    pub procedure: Option<String>,
+    pub call_site: Option<Span>,
+    pub arguments: Vec<Expression>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}", self.procedure.as_deref().unwrap_or("#procedure"))?;
+        for argument in &self.arguments {
+            write!(f, " {}", argument)?;
+        }
+        write!(f, ")")?;
+        if let Some(span) = self.call_site {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Details {
+    kind: ErrorKind,
+    span: Option<Span>,
+    backtrace: Vec<Frame>,
+}
+
+/// An error together with where it occurred. The details are boxed to keep
+/// `Result`s returned throughout the interpreter small.
+#[derive(Debug)]
+pub struct SchemeError(Box<Details>);
+
+impl SchemeError {
+    pub fn syntax(message: &str) -> Self {
+        ErrorKind::Syntax(message.to_string()).into()
+    }
+
+    pub fn incomplete(message: &str) -> Self {
+        ErrorKind::Incomplete(message.to_string()).into()
+    }
+
+    pub fn wrong_type(expected: &'static str, actual: &Expression) -> Self {
+        ErrorKind::WrongType {
+            expected,
+            actual: actual.clone(),
+        }
+        .into()
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.0.kind
+    }
+
+    /// Where in the source the error occurred, if known.
+    pub fn span(&self) -> Option<Span> {
+        self.0.span
+    }
+
+    /// The calls that were in progress, innermost first. Calls made in tail
+    /// position have already returned and do not appear.
+    pub fn backtrace(&self) -> &[Frame] {
+        &self.0.backtrace
+    }
+
+    /// Attaches a location to the error unless it already has a more precise one.
+    pub fn at(mut self, span: Span) -> Self {
+        self.0.span.get_or_insert(span);
+        self
+    }
+
+    pub(crate) fn push_frame(&mut self, frame: Frame) {
+        self.0.backtrace.push(frame);
+    }
+
+    /// Formats the error together with an excerpt of `source` pointing out
+    /// where it occurred, followed by the backtrace. The excerpt is left out
+    /// if the error occurred in some other source than `id`.
+    pub fn report(&self, source: &str, id: SourceId) -> String {
+        let mut report = self.to_string();
+        let excerpt = self
+            .span()
+            .filter(|span| span.source == id)
+            .and_then(|span| span.excerpt(source));
+        if let Some(excerpt) = excerpt {
+            report.push('\n');
+            report.push_str(&excerpt);
+        }
+        if !self.backtrace().is_empty() {
+            report.push_str("\nBacktrace:");
+            for frame in self.backtrace().iter().take(MAX_REPORTED_FRAMES) {
+                report.push_str(&format!("\n  {}", frame));
+            }
+            if self.backtrace().len() > MAX_REPORTED_FRAMES {
+                let omitted = self.backtrace().len() - MAX_REPORTED_FRAMES;
+                report.push_str(&format!("\n  ... and {} more", omitted));
+            }
+        }
+        report
+    }
+}
+
+impl From<ErrorKind> for SchemeError {
+    fn from(kind: ErrorKind) -> Self {
+        SchemeError(Box::new(Details {
+            kind,
+            span: None,
+            backtrace: Vec::new(),
+        }))
+    }
+}
+
+impl fmt::Display for SchemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind())?;
+        if let Some(span) = self.span() {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Syntax(message) => write!(f, "Invalid syntax: {}", message),
+            ErrorKind::Incomplete(message) => write!(f, "Unexpected end of input: {}", message),
+            ErrorKind::UnboundVariable(name) => write!(f, "Unbound variable '{}'", name),
+            ErrorKind::WrongType { expected, actual } => {
+                write!(f, "Expecting {}, got '{}'", expected, actual)
+            }
+            ErrorKind::Arity {
+                procedure,
+                min,
+                max,
+                actual,
+            } => {
+                write!(
+                    f,
+                    "Incorrect argument count in call ({}): expected ",
+                    procedure
+                )?;
+                match max {
+                    Some(max) if max == min => write!(f, "{}", min)?,
+                    Some(max) => write!(f, "{} to {}", min, max)?,
+                    None => write!(f, "at least {}", min)?,
+                }
+                write!(f, ", got {}", actual)
+            }
+            ErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            ErrorKind::ImplementationRestriction(message) => {
+                write!(f, "Implementation restriction: {}", message)
+            }
+            ErrorKind::User { message, irritants } => {
+                write!(f, "{}", message)?;
+                for irritant in irritants {
+                    write!(f, " {}", irritant)?;
+                }
+                Ok(())
+            }
+            ErrorKind::Io(err) => write!(f, "I/O error: {}", err),
+            ErrorKind::Exit(status) => write!(f, "Exit with status {}", status),
+        }
+    }
+}
+
+impl Error for SchemeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.kind() {
+            ErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SchemeError {
+    fn from(err: io::Error) -> Self {
+        ErrorKind::Io(err).into()
+    }
+}
@@ -1,121 +1,711 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
 use crate::environment::Environment;
-use crate::expression::Expression;
+use crate::error::{ErrorKind, Frame, SchemeError};
+use crate::expression::{Expression, Lambda};
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+/// The outcome of evaluating a form whose result may be another expression in
+/// tail position. Returning that expression to the loop in `eval`, instead of
+/// evaluating it recursively, keeps tail calls from growing the Rust stack.
+enum Tail {
+    Value(Expression),
+    Eval(Expression, Environment),
+    Call(Expression, Vec<Expression>),
+}
+
+fn invalid_syntax<T>(form: &str) -> Result<T, SchemeError> {
+    Err(ErrorKind::Syntax(format!("malformed '{}'", form)).into())
+}
+
+pub(crate) fn is_true(value: &Expression) -> bool {
+    !matches!(value, Expression::BooleanLiteral(false))
+}
+
+/// Reads a parameter list such as `(a b)` or `(a b . rest)`, returning the
+/// required parameters and the one bound to the remaining arguments.
+fn parameter_names(params: &[Expression]) -> Result<(Vec<Symbol>, Option<Symbol>), SchemeError> {
+    let (required, rest) = match params {
+        [init @ .., Expression::Identifier(dot, _), rest] if dot.as_str() == "." => {
+            (init, Some(rest))
+        }
+        _ => (params, None),
+    };
+    let mut names: Vec<Symbol> = Vec::new();
+    for param in required.iter().chain(rest) {
+        match param {
+            Expression::Identifier(name, _) if name.as_str() != "." => {
+                if names.contains(name) {
+                    let message = format!("duplicate parameter '{}'", name);
+                    return Err(SchemeError::syntax(&message));
+                }
+                names.push(name.clone());
+            }
+            _ => return Err(SchemeError::syntax("parameters must be identifiers")),
+        }
+    }
+    let rest = rest.and_then(|_| names.pop());
+    Ok((names, rest))
+}
+
+fn eval_sequence(exprs: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    match exprs.split_last() {
+        Some((last, init)) => {
+            for expr in init {
+                eval(expr, env)?;
+            }
+            Ok(Tail::Eval(last.clone(), env.clone()))
+        }
+        None => Ok(Tail::Value(Expression::Void)),
+    }
+}
+
+fn make_procedure(
+    name: Option<Symbol>,
+    (params, rest): (Vec<Symbol>, Option<Symbol>),
+    body: &[Expression],
+    env: &Environment,
+) -> Result<Expression, SchemeError> {
+    if body.is_empty() {
+        return Err(SchemeError::syntax("empty body"));
+    }
+    Ok(Expression::Procedure(Rc::new(Lambda {
+        name,
+        params,
+        rest,
+        body: body.to_vec(),
+        env: env.clone(),
+    })))
+}
+
+/// Evaluates a non-empty body, such as that of a procedure or `let`.
+fn eval_body(body: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    if body.is_empty() {
+        return Err(SchemeError::syntax("empty body"));
+    }
+    eval_sequence(body, env)
+}
+
+fn eval_define(args: &[Expression], env: &Environment) -> Result<Expression, SchemeError> {
+    match args {
+        [Expression::Identifier(name, _), value_expr] => {
+            let value = match special_form_operands(value_expr, "lambda") {
+                Some(lambda_args) => eval_lambda(lambda_args, env, Some(name.clone()))?,
+                None => eval(value_expr, env)?,
+            };
+            env.define(name.clone(), value);
+        }
+        [Expression::Combination(comb, _), body @ ..] => {
+            let (proc_name, params) = match comb.split_first() {
+                Some((Expression::Identifier(name, _), params)) if name.as_str() != "." => {
+                    (name, params)
+                }
+                _ => return invalid_syntax("define"),
+            };
+            let procedure =
+                make_procedure(Some(proc_name.clone()), parameter_names(params)?, body, env)?;
+            env.define(proc_name.clone(), procedure);
+        }
+        _ => return invalid_syntax("define"),
+    }
+    Ok(Expression::Void)
+}
+
+fn eval_set(args: &[Expression], env: &Environment) -> Result<Expression, SchemeError> {
+    match args {
+        [Expression::Identifier(name, _), value_expr] => {
+            let value = eval(value_expr, env)?;
+            env.set(name, value)?;
+            Ok(Expression::Void)
+        }
+        _ => invalid_syntax("set!"),
+    }
+}
+
+fn eval_lambda(
+    args: &[Expression],
+    env: &Environment,
+    name: Option<Symbol>,
+) -> Result<Expression, SchemeError> {
+    match args {
+        [Expression::Combination(params, _), body @ ..] => match params.first() {
+            Some(Expression::Identifier(dot, _)) if dot.as_str() == "." => invalid_syntax("lambda"),
+            _ => make_procedure(name, parameter_names(params)?, body, env),
+        },
+        [Expression::Identifier(rest, _), body @ ..] if rest.as_str() != "." => {
+            make_procedure(name, (Vec::new(), Some(rest.clone())), body, env)
+        }
+        _ => invalid_syntax("lambda"),
+    }
+}
+
+fn parse_bindings(bindings: &Expression) -> Result<Vec<(Symbol, &Expression)>, SchemeError> {
+    match bindings {
+        Expression::Combination(bindings, _) => bindings
+            .iter()
+            .map(|binding| match binding {
+                Expression::Combination(pair, _) => match pair.as_ref() {
+                    [Expression::Identifier(name, _), init] => Ok((name.clone(), init)),
+                    _ => Err(SchemeError::syntax("malformed binding list")),
+                },
+                _ => Err(SchemeError::syntax("malformed binding list")),
+            })
+            .collect(),
+        _ => Err(SchemeError::syntax("malformed binding list")),
+    }
+}
+
+/// Reads a binding list whose names must all differ, as for `let` and
+/// `letrec`, which bind them in a single scope.
+fn parse_distinct_bindings(
+    bindings: &Expression,
+) -> Result<Vec<(Symbol, &Expression)>, SchemeError> {
+    let bindings = parse_bindings(bindings)?;
+    for (i, (name, _)) in bindings.iter().enumerate() {
+        if bindings[..i].iter().any(|(other, _)| other == name) {
+            let message = format!("duplicate binding '{}'", name);
+            return Err(SchemeError::syntax(&message));
+        }
+    }
+    Ok(bindings)
+}
 
-pub fn eval(expr: &Expression, env: &mut Environment) -> Result<Expression, String> {
+fn eval_let(args: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    if let [Expression::Identifier(name, _), bindings, body @ ..] = args {
+        return eval_named_let(name, bindings, body, env);
+    }
+    let (bindings, body) = match args.split_first() {
+        Some(split) => split,
+        None => return invalid_syntax("let"),
+    };
+    let bindings = parse_distinct_bindings(bindings)?;
+    let let_env = env.extend();
+    for (name, init) in bindings {
+        let_env.define(name, eval(init, env)?);
+    }
+    eval_body(body, &let_env)
+}
+
+fn eval_named_let(
+    name: &Symbol,
+    bindings: &Expression,
+    body: &[Expression],
+    env: &Environment,
+) -> Result<Tail, SchemeError> {
+    let bindings = parse_distinct_bindings(bindings)?;
+    let args = bindings
+        .iter()
+        .map(|(_, init)| eval(init, env))
+        .collect::<Result<Vec<_>, SchemeError>>()?;
+    let params = bindings.into_iter().map(|(name, _)| name).collect();
+    let loop_env = env.extend();
+    let procedure = make_procedure(Some(name.clone()), (params, None), body, &loop_env)?;
+    loop_env.define(name.clone(), procedure.clone());
+    Ok(Tail::Call(procedure, args))
+}
+
+fn eval_let_star(args: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    let (bindings, body) = match args.split_first() {
+        Some(split) => split,
+        None => return invalid_syntax("let*"),
+    };
+    let mut let_env = env.extend();
+    for (name, init) in parse_bindings(bindings)? {
+        let value = eval(init, &let_env)?;
+        let_env = let_env.extend();
+        let_env.define(name, value);
+    }
+    eval_body(body, &let_env)
+}
+
+fn eval_letrec(args: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    let (bindings, body) = match args.split_first() {
+        Some(split) => split,
+        None => return invalid_syntax("letrec"),
+    };
+    let bindings = parse_distinct_bindings(bindings)?;
+    let let_env = env.extend();
+    for (name, _) in &bindings {
+        let_env.define(name.clone(), Expression::Void);
+    }
+    for (name, init) in bindings {
+        let value = eval(init, &let_env)?;
+        let_env.define(name, value);
+    }
+    eval_body(body, &let_env)
+}
+
+/// Splits syntax such as `(a b . c)` into its elements and its dotted tail.
+fn split_dotted(
+    elements: &[Expression],
+) -> Result<(&[Expression], Option<&Expression>), SchemeError> {
+    let is_dot = |e: &Expression| matches!(e, Expression::Identifier(id, _) if id.as_str() == ".");
+    let (init, tail) = match elements {
+        [init @ .., dot, tail] if is_dot(dot) => (init, Some(tail)),
+        _ => (elements, None),
+    };
+    if (init.is_empty() && tail.is_some()) || init.iter().chain(tail).any(is_dot) {
+        return Err(SchemeError::syntax("misplaced '.'"));
+    }
+    Ok((init, tail))
+}
+
+/// Converts parsed syntax into the data it denotes, as done by `quote`. The
+/// result is freshly allocated, so mutating it leaves the literal unchanged.
+fn syntax_to_datum(expr: &Expression) -> Result<Expression, SchemeError> {
     match expr {
-        Expression::Identifier(id) => match env.lookup(id) {
-            Some(e) => Ok(e.clone()),
-            None => Err(format!("Undefined symbol '{}'", id)),
+        Expression::Combination(elements, _) => {
+            let (elements, tail) = split_dotted(elements)?;
+            let tail = match tail {
+                Some(tail) => syntax_to_datum(tail)?,
+                None => Expression::EmptyList,
+            };
+            elements.iter().try_rfold(tail, |tail, e| {
+                Ok(Expression::cons(syntax_to_datum(e)?, tail))
+            })
+        }
+        Expression::Identifier(id, _) => Ok(Expression::Symbol(id.clone())),
+        Expression::Vector(elements) => {
+            let elements = elements
+                .borrow()
+                .iter()
+                .map(syntax_to_datum)
+                .collect::<Result<Vec<_>, SchemeError>>()?;
+            Ok(Expression::Vector(Rc::new(RefCell::new(elements))))
+        }
+        Expression::StringLiteral(s) => Ok(Expression::string(s.borrow().clone())),
+        Expression::Bytevector(bytes) => Ok(Expression::Bytevector(Rc::new(RefCell::new(
+            bytes.borrow().clone(),
+        )))),
+        other => Ok(other.clone()),
+    }
+}
+
+fn eval_quote(args: &[Expression]) -> Result<Expression, SchemeError> {
+    match args {
+        [datum] => syntax_to_datum(datum),
+        _ => invalid_syntax("quote"),
+    }
+}
+
+/// Returns the operands if `expr` is a form `(keyword operands...)`.
+fn special_form_operands<'a>(expr: &'a Expression, keyword: &str) -> Option<&'a [Expression]> {
+    match expr {
+        Expression::Combination(elements, _) => match elements.as_ref() {
+            [Expression::Identifier(id, _), operands @ ..] if id.as_str() == keyword => {
+                Some(operands)
+            }
+            _ => None,
         },
-        Expression::Combination(elements) => {
-            let mut elem_iter = elements.iter();
-            if let Some(first_expr) = elem_iter.next() {
-                if let Expression::Identifier(id) = first_expr {
-                    if id == "define" {
-                        let name_expr = elem_iter.next().ok_or("Invalid syntax".to_string())?;
-                        let body_expr = elem_iter.next().ok_or("Invalid syntax".to_string())?;
-                        if !elem_iter.next().is_none() {
-                            return Err("Invalid syntax".to_string());
-                        }
-                        if let Expression::Identifier(name) = name_expr {
-                            let body_value = eval(body_expr, env)?;
-                            env.insert(name.clone(), body_value);
-                        } else if let Expression::Combination(comb) = name_expr {
-                            let mut ids = comb
-                                .iter()
-                                .map(|e| match e {
-                                    Expression::Identifier(n) => Some(n.clone()),
-                                    _ => None,
-                                })
-                                .collect::<Option<Vec<String>>>()
-                                .ok_or("Invalid syntax".to_string())?;
-                            if ids.is_empty() {
-                                return Err("Invalid syntax".to_string());
-                            }
-                            let proc_name = ids.remove(0);
-                            env.insert(
-                                proc_name,
-                                Expression::Procedure(ids, Box::new(body_expr.clone())),
-                            );
-                        } else {
-                            return Err("Invalid syntax".to_string());
-                        }
-                        return Ok(Expression::Void);
-                    } else if id == "cond" {
-                        for clause in elem_iter {
-                            match clause {
-                                Expression::Combination(e) => {
-                                    if e.len() != 2 {
-                                        return Err("Expecting pair as cond clause".to_string());
-                                    }
-                                    let predicate = eval(&e[0], env)?;
-                                    match predicate {
-                                        Expression::BooleanLiteral(false) => {}
-                                        _ => {
-                                            let value = eval(&e[1], env)?;
-                                            return Ok(value);
-                                        }
-                                    }
-                                }
-                                _ => return Err("Invalid syntax".to_string()),
-                            }
-                        }
-                        // TODO return unspecified
-                        return Ok(Expression::Void);
+        _ => None,
+    }
+}
+
+/// Returns the operand if `expr` is the two-element form `(keyword operand)`.
+fn special_form_operand<'a>(expr: &'a Expression, keyword: &str) -> Option<&'a Expression> {
+    match special_form_operands(expr, keyword) {
+        Some([operand]) => Some(operand),
+        _ => None,
+    }
+}
+
+fn quasiquote(
+    template: &Expression,
+    depth: usize,
+    env: &Environment,
+) -> Result<Expression, SchemeError> {
+    if let Some(operand) = special_form_operand(template, "unquote") {
+        if depth == 1 {
+            return eval(operand, env);
+        }
+        return Ok(Expression::list(vec![
+            Expression::Symbol(Symbol::new("unquote")),
+            quasiquote(operand, depth - 1, env)?,
+        ]));
+    }
+    if let Some(operand) = special_form_operand(template, "quasiquote") {
+        return Ok(Expression::list(vec![
+            Expression::Symbol(Symbol::new("quasiquote")),
+            quasiquote(operand, depth + 1, env)?,
+        ]));
+    }
+    match template {
+        Expression::Combination(elements, _) => {
+            let (elements, tail) = split_dotted(elements)?;
+            let tail = match tail {
+                Some(tail) => quasiquote(tail, depth, env)?,
+                None => Expression::EmptyList,
+            };
+            quasiquote_elements(elements, tail, depth, env)
+        }
+        Expression::Vector(elements) => {
+            let elements = elements.borrow().clone();
+            let list = quasiquote_elements(&elements, Expression::EmptyList, depth, env)?;
+            let elements = list.list_to_vec().unwrap_or_default();
+            Ok(Expression::Vector(Rc::new(RefCell::new(elements))))
+        }
+        other => syntax_to_datum(other),
+    }
+}
+
+/// Builds the list of the quasiquoted `elements` followed by `tail`, splicing
+/// in the values of `unquote-splicing` forms.
+fn quasiquote_elements(
+    elements: &[Expression],
+    tail: Expression,
+    depth: usize,
+    env: &Environment,
+) -> Result<Expression, SchemeError> {
+    let mut result = tail;
+    for element in elements.iter().rev() {
+        match special_form_operand(element, "unquote-splicing") {
+            Some(operand) if depth == 1 => {
+                let value = eval(operand, env)?;
+                let spliced = value.list_to_vec().ok_or_else(|| {
+                    let err = SchemeError::wrong_type("list", &value);
+                    match element {
+                        Expression::Combination(_, span) => err.at(*span),
+                        _ => err,
                     }
+                })?;
+                result = spliced
+                    .into_iter()
+                    .rev()
+                    .fold(result, |tail, e| Expression::cons(e, tail));
+            }
+            Some(operand) => {
+                let inner = Expression::list(vec![
+                    Expression::Symbol(Symbol::new("unquote-splicing")),
+                    quasiquote(operand, depth - 1, env)?,
+                ]);
+                result = Expression::cons(inner, result);
+            }
+            None => result = Expression::cons(quasiquote(element, depth, env)?, result),
+        }
+    }
+    Ok(result)
+}
+
+fn eval_quasiquote(args: &[Expression], env: &Environment) -> Result<Expression, SchemeError> {
+    match args {
+        [template] => quasiquote(template, 1, env),
+        _ => invalid_syntax("quasiquote"),
+    }
+}
+
+fn eval_if(args: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    let (test, consequent, alternative) = match args {
+        [test, consequent] => (test, consequent, None),
+        [test, consequent, alternative] => (test, consequent, Some(alternative)),
+        _ => return invalid_syntax("if"),
+    };
+    if is_true(&eval(test, env)?) {
+        Ok(Tail::Eval(consequent.clone(), env.clone()))
+    } else if let Some(alternative) = alternative {
+        Ok(Tail::Eval(alternative.clone(), env.clone()))
+    } else {
+        Ok(Tail::Value(Expression::Void))
+    }
+}
+
+fn eval_and(args: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    let (last, init) = match args.split_last() {
+        Some(split) => split,
+        None => return Ok(Tail::Value(Expression::BooleanLiteral(true))),
+    };
+    for expr in init {
+        let value = eval(expr, env)?;
+        if !is_true(&value) {
+            return Ok(Tail::Value(value));
+        }
+    }
+    Ok(Tail::Eval(last.clone(), env.clone()))
+}
+
+fn eval_or(args: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    let (last, init) = match args.split_last() {
+        Some(split) => split,
+        None => return Ok(Tail::Value(Expression::BooleanLiteral(false))),
+    };
+    for expr in init {
+        let value = eval(expr, env)?;
+        if is_true(&value) {
+            return Ok(Tail::Value(value));
+        }
+    }
+    Ok(Tail::Eval(last.clone(), env.clone()))
+}
+
+fn eval_when(args: &[Expression], env: &Environment, expected: bool) -> Result<Tail, SchemeError> {
+    match args.split_first() {
+        Some((test, body)) => {
+            if is_true(&eval(test, env)?) == expected {
+                eval_sequence(body, env)
+            } else {
+                Ok(Tail::Value(Expression::Void))
+            }
+        }
+        None => invalid_syntax(if expected { "when" } else { "unless" }),
+    }
+}
+
+fn eval_cond(args: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    for (index, clause) in args.iter().enumerate() {
+        let clause = match clause {
+            Expression::Combination(clause, _) if !clause.is_empty() => clause,
+            _ => return invalid_syntax("cond"),
+        };
+        if let Expression::Identifier(id, _) = &clause[0] {
+            if id.as_str() == "else" {
+                if index + 1 != args.len() {
+                    return Err(SchemeError::syntax("'else' must be the last cond clause"));
                 }
-                let operand = eval(first_expr, env)?;
-                match operand {
-                    Expression::BuiltinProcedure(p) => {
-                        // run eval on arguments
-                        let args = elem_iter
-                            .map(|e| eval(e, env))
-                            .collect::<Result<Vec<_>, String>>()?;
-                        p(args)
-                    }
-                    Expression::Procedure(arg_names, body) => {
-                        let args = elem_iter
-                            .map(|e| eval(e, env))
-                            .collect::<Result<Vec<_>, String>>()?;
-                        if arg_names.len() != args.len() {
-                            return Err("Wrong number of arguments".to_string());
-                        }
-                        env.push();
-                        for (var, value) in arg_names.iter().zip(args.iter()) {
-                            env.insert(var.clone(), value.clone());
-                        }
-                        let result = eval(body.as_ref(), env);
-                        env.pop();
-                        result
-                    }
-                    _ => Err(format!("Attempt to apply non-procedure '{}'", operand)),
+                return eval_sequence(&clause[1..], env);
+            }
+        }
+        let test_value = eval(&clause[0], env)?;
+        if !is_true(&test_value) {
+            continue;
+        }
+        return match &clause[1..] {
+            [] => Ok(Tail::Value(test_value)),
+            [Expression::Identifier(arrow, _), receiver] if arrow.as_str() == "=>" => {
+                let receiver = eval(receiver, env)?;
+                Ok(Tail::Call(receiver, vec![test_value]))
+            }
+            body => eval_sequence(body, env),
+        };
+    }
+    Ok(Tail::Value(Expression::Void))
+}
+
+/// A procedure call whose body is being evaluated.
+struct ActiveCall {
+    procedure: Rc<Lambda>,
+    env: Environment,
+    call_site: Option<Span>,
+}
+
+/// Records `call` in the backtrace of an error propagating out of it.
+fn unwind(mut err: SchemeError, call: Option<ActiveCall>) -> SchemeError {
+    if let Some(call) = call {
+        let arguments = call
+            .procedure
+            .params
+            .iter()
+            .chain(&call.procedure.rest)
+            .filter_map(|param| call.env.lookup(param))
+            .collect();
+        err.push_frame(Frame {
+            procedure: call.procedure.name.as_ref().map(|name| name.to_string()),
+            call_site: call.call_site,
+            arguments,
+        });
+    }
+    err
+}
+
+/// Binds the arguments of a call and returns the procedure body's final
+/// expression as a tail, or the result directly for builtins. Entering a
+/// procedure replaces `call`, which is how tail calls drop out of backtraces.
+fn enter_call(
+    procedure: Expression,
+    args: Vec<Expression>,
+    call_site: Option<Span>,
+    call: &mut Option<ActiveCall>,
+) -> Result<Tail, SchemeError> {
+    match procedure {
+        Expression::BuiltinProcedure(p) => Ok(Tail::Value(p(args)?)),
+        Expression::Procedure(lambda) => {
+            let (min, max) = match lambda.rest {
+                Some(_) => (lambda.params.len(), None),
+                None => (lambda.params.len(), Some(lambda.params.len())),
+            };
+            if args.len() < min || max.is_some_and(|max| args.len() > max) {
+                return Err(ErrorKind::Arity {
+                    procedure: match &lambda.name {
+                        Some(name) => name.to_string(),
+                        None => "#procedure".to_string(),
+                    },
+                    min,
+                    max,
+                    actual: args.len(),
                 }
-            } else {
-                Err("Invalid syntax ()".to_string())
+                .into());
+            }
+            let call_env = lambda.env.extend();
+            let mut args = args.into_iter();
+            for (var, value) in lambda.params.iter().zip(&mut args) {
+                call_env.define(var.clone(), value);
+            }
+            if let Some(rest) = &lambda.rest {
+                call_env.define(rest.clone(), Expression::list(args.collect::<Vec<_>>()));
             }
+            *call = Some(ActiveCall {
+                procedure: lambda.clone(),
+                env: call_env.clone(),
+                call_site,
+            });
+            eval_sequence(&lambda.body, &call_env)
+        }
+        _ => Err(SchemeError::wrong_type("procedure", &procedure)),
+    }
+}
+
+pub fn apply(procedure: Expression, args: Vec<Expression>) -> Result<Expression, SchemeError> {
+    let mut call = None;
+    match enter_call(procedure, args, None, &mut call) {
+        Ok(Tail::Eval(expr, env)) => eval(&expr, &env).map_err(|err| unwind(err, call)),
+        Ok(Tail::Value(value)) => Ok(value),
+        Ok(Tail::Call(..)) => unreachable!("entering a procedure never yields a call"),
+        Err(err) => Err(unwind(err, call)),
+    }
+}
+
+/// Evaluates a single combination, handing any expression in tail position
+/// back to the loop in `eval`.
+fn eval_combination(elements: &[Expression], env: &Environment) -> Result<Tail, SchemeError> {
+    let (first_expr, args) = match elements.split_first() {
+        Some(split) => split,
+        None => return Err(SchemeError::syntax("empty combination ()")),
+    };
+    if let Expression::Identifier(id, _) = first_expr {
+        match id.as_str() {
+            "define" => return eval_define(args, env).map(Tail::Value),
+            "set!" => return eval_set(args, env).map(Tail::Value),
+            "lambda" => return eval_lambda(args, env, None).map(Tail::Value),
+            "quote" => return eval_quote(args).map(Tail::Value),
+            "quasiquote" => return eval_quasiquote(args, env).map(Tail::Value),
+            "unquote" | "unquote-splicing" => {
+                return Err(ErrorKind::Syntax(format!("'{}' outside of quasiquote", id)).into())
+            }
+            "let" => return eval_let(args, env),
+            "let*" => return eval_let_star(args, env),
+            "letrec" | "letrec*" => return eval_letrec(args, env),
+            "if" => return eval_if(args, env),
+            "cond" => return eval_cond(args, env),
+            "and" => return eval_and(args, env),
+            "or" => return eval_or(args, env),
+            "when" => return eval_when(args, env, true),
+            "unless" => return eval_when(args, env, false),
+            "begin" => return eval_sequence(args, env),
+            _ => {}
+        }
+    }
+    let operand = eval(first_expr, env)?;
+    let args = args
+        .iter()
+        .map(|e| eval(e, env))
+        .collect::<Result<Vec<_>, SchemeError>>()?;
+    Ok(Tail::Call(operand, args))
+}
+
+/// The deepest nesting of non-tail evaluations allowed before giving up with
+/// an error rather than overflowing the native stack.
+pub const MAX_EVAL_DEPTH: usize = 10_000;
+
+/// A native stack size that leaves room for `MAX_EVAL_DEPTH` nested
+/// evaluations, for threads that run arbitrary programs.
+pub const EVAL_STACK_SIZE: usize = 256 << 20;
+
+thread_local! {
+    /// The number of calls to `eval` in progress on this thread.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+pub fn eval(expr: &Expression, env: &Environment) -> Result<Expression, SchemeError> {
+    let depth = DEPTH.with(Cell::get);
+    if depth == MAX_EVAL_DEPTH {
+        let message = "recursion too deep".to_string();
+        return Err(ErrorKind::ImplementationRestriction(message).into());
+    }
+    DEPTH.with(|d| d.set(depth + 1));
+    let result = eval_loop(expr, env);
+    DEPTH.with(|d| d.set(depth));
+    result
+}
+
+fn eval_loop(expr: &Expression, env: &Environment) -> Result<Expression, SchemeError> {
+    let mut expr = expr.clone();
+    let mut env = env.clone();
+    let mut call = None;
+    loop {
+        let (elements, span) = match &expr {
+            Expression::Identifier(id, span) => {
+                return match env.lookup(id) {
+                    Some(e) => Ok(e),
+                    None => Err(unwind(
+                        SchemeError::from(ErrorKind::UnboundVariable(id.to_string())).at(*span),
+                        call,
+                    )),
+                }
+            }
+            Expression::Combination(elements, span) => (elements.clone(), *span),
+            // Vector literals are self-evaluating, with their elements quoted.
+            // Like strings and bytevectors, each evaluation yields a copy.
+            Expression::Vector(_) | Expression::StringLiteral(_) | Expression::Bytevector(_) => {
+                return syntax_to_datum(&expr)
+            }
+            other => return Ok(other.clone()),
+        };
+        let mut tail = eval_combination(&elements, &env);
+        if let Ok(Tail::Call(procedure, args)) = tail {
+            tail = enter_call(procedure, args, Some(span), &mut call);
+        }
+        match tail {
+            Ok(Tail::Value(value)) => return Ok(value),
+            Ok(Tail::Eval(next_expr, next_env)) => {
+                expr = next_expr;
+                env = next_env;
+            }
+            Ok(Tail::Call(..)) => unreachable!("entering a procedure never yields a call"),
+            Err(err) => return Err(unwind(err.at(span), call)),
         }
-        other => Ok(other.clone()),
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::eval;
+    use super::{eval, EVAL_STACK_SIZE};
     use crate::environment::create_root_environment;
+    use crate::error::{ErrorKind, SchemeError};
     use crate::expression::Expression;
     use crate::number::Number;
-    use crate::parser::Parser;
+    use crate::parser::{parse, Parser};
+    use crate::span::SourceId;
+    use crate::symbol::Symbol;
     use crate::tokenizer::tokenize;
+    use std::time::{Duration, Instant};
 
     fn single_expr_eq(input: &str, expected: Expression) {
-        let tokens = tokenize(input.chars());
-        let mut root_env = create_root_environment();
+        let tokens = tokenize(input.chars(), SourceId::fresh());
+        let root_env = create_root_environment();
         let parser = Parser::new(tokens);
-        let results: Result<Vec<Expression>, String> =
-            parser.map(|e| eval(&e.unwrap(), &mut root_env)).collect();
+        let results: Result<Vec<Expression>, SchemeError> =
+            parser.map(|e| eval(&e.unwrap(), &root_env)).collect();
         assert_eq!(results.unwrap().last().unwrap(), &expected);
     }
 
+    fn single_expr_err(input: &str) -> SchemeError {
+        let tokens = tokenize(input.chars(), SourceId::fresh());
+        let root_env = create_root_environment();
+        let parser = Parser::new(tokens);
+        let results: Result<Vec<Expression>, SchemeError> =
+            parser.map(|e| eval(&e?, &root_env)).collect();
+        results.unwrap_err()
+    }
+
+    fn single_expr_display(input: &str, expected: &str) {
+        let tokens = tokenize(input.chars(), SourceId::fresh());
+        let root_env = create_root_environment();
+        let parser = Parser::new(tokens);
+        let results: Result<Vec<Expression>, SchemeError> =
+            parser.map(|e| eval(&e.unwrap(), &root_env)).collect();
+        assert_eq!(format!("{}", results.unwrap().last().unwrap()), expected);
+    }
+
     fn int_expr(v: i64) -> Expression {
         Expression::NumberLiteral(Number::from(v))
     }
@@ -150,6 +740,249 @@ mod test {
         single_expr_eq("(/ 10 5)", int_expr(2));
     }
 
+    #[test]
+    fn exact_rationals() {
+        single_expr_display("(/ 1 3)", "1/3");
+        single_expr_display("(+ 1/6 1/3)", "1/2");
+        single_expr_eq("(* 2/3 3/2)", int_expr(1));
+        single_expr_display("(/ 6 -4)", "-3/2");
+        single_expr_display("(/ 99999999999999999999 2)", "99999999999999999999/2");
+        single_expr_eq("(< 1/3 0.34 1/2)", Expression::BooleanLiteral(true));
+        single_expr_eq("(= 1/2 0.5)", Expression::BooleanLiteral(true));
+        single_expr_display("(numerator 6/4)", "3");
+        single_expr_display("(denominator 6/4)", "2");
+        single_expr_display("(denominator 5)", "1");
+        single_expr_display("(exact 0.25)", "1/4");
+        single_expr_eq("(exact? 1/3)", Expression::BooleanLiteral(true));
+        single_expr_eq("(inexact? (+ 1/3 0.5))", Expression::BooleanLiteral(true));
+        single_expr_eq("(exact? (inexact 1/3))", Expression::BooleanLiteral(false));
+    }
+
+    #[test]
+    fn complex_numbers() {
+        single_expr_display("(sqrt -1)", "+i");
+        single_expr_display("(sqrt -4)", "+2i");
+        single_expr_display("(+ 3+4i 1-i)", "4+3i");
+        single_expr_eq("(* 3+4i 3-4i)", int_expr(25));
+        single_expr_display("(/ 1 +2i)", "-1/2i");
+        single_expr_display("(make-rectangular 1/2 -1)", "1/2-i");
+        single_expr_eq("(magnitude 3+4i)", int_expr(5));
+        single_expr_eq("(real-part 3+4i)", int_expr(3));
+        single_expr_eq("(imag-part 3+4i)", int_expr(4));
+        single_expr_display("(imag-part 2.5)", "0.0");
+        single_expr_eq("(= (make-polar 2 0) 2)", Expression::BooleanLiteral(true));
+        single_expr_eq(
+            "(< -1e-12 (- (angle 1@0.5) 0.5) 1e-12)",
+            Expression::BooleanLiteral(true),
+        );
+        single_expr_eq("(exact? 1/2+i)", Expression::BooleanLiteral(true));
+        single_expr_eq("(exact? 1.5+i)", Expression::BooleanLiteral(false));
+        single_expr_err("(< +i 1)");
+    }
+
+    #[test]
+    fn numeric_library() {
+        single_expr_eq("(<= 1 1 2)", Expression::BooleanLiteral(true));
+        single_expr_eq("(>= 3 4)", Expression::BooleanLiteral(false));
+        single_expr_eq("(quotient 17 -5)", int_expr(-3));
+        single_expr_eq("(remainder 17 -5)", int_expr(2));
+        single_expr_eq("(modulo 17 -5)", int_expr(-3));
+        single_expr_display("(floor/ -7 2)", "(-4 1)");
+        single_expr_display("(truncate/ -7 2)", "(-3 -1)");
+        single_expr_eq("(gcd 12 -18 8)", int_expr(2));
+        single_expr_eq("(lcm 4 6 -10)", int_expr(60));
+        single_expr_display("(abs -7/2)", "7/2");
+        single_expr_eq("(max 3 1/2)", int_expr(3));
+        single_expr_eq("(exact? (min 1 2.0))", Expression::BooleanLiteral(false));
+        single_expr_eq("(floor -7/2)", int_expr(-4));
+        single_expr_eq("(ceiling -7/2)", int_expr(-3));
+        single_expr_eq("(round 5/2)", int_expr(2));
+        single_expr_eq("(round 7/2)", int_expr(4));
+        single_expr_eq("(= (round -2.5) -2)", Expression::BooleanLiteral(true));
+        single_expr_eq("(truncate -5/2)", int_expr(-2));
+        single_expr_display("(exact-integer-sqrt 17)", "(4 1)");
+        single_expr_display("(sqrt 16/9)", "4/3");
+        single_expr_display("(expt 2 100)", "1267650600228229401496703205376");
+        single_expr_display("(expt 2/3 -3)", "27/8");
+        single_expr_eq("(exact? (expt 4 0.5))", Expression::BooleanLiteral(false));
+        single_expr_display("(expt 0.0 -1)", "+inf.0");
+        single_expr_display("(expt 0 -1.5)", "+inf.0");
+        single_expr_display("(expt 0 1.5)", "0.0");
+        single_expr_display("(sqrt (expt 10 400))", &format!("1{}", "0".repeat(200)));
+        single_expr_display("(sqrt (expt 10 401))", "3.1622776601683794e200");
+        single_expr_display("(sqrt (/ 1 (expt 10 401)))", "3.1622776601683792e-201");
+        single_expr_display("(square 1/3)", "1/9");
+        single_expr_eq("(= (exp 0) 1)", Expression::BooleanLiteral(true));
+        single_expr_eq("(= (log 8 2) 3)", Expression::BooleanLiteral(true));
+        single_expr_eq(
+            "(< 3.14 (* 4 (atan 1)) 3.15)",
+            Expression::BooleanLiteral(true),
+        );
+        single_expr_eq("(integer? 2.0)", Expression::BooleanLiteral(true));
+        single_expr_eq("(exact-integer? 2.0)", Expression::BooleanLiteral(false));
+        single_expr_eq("(rational? 1/2)", Expression::BooleanLiteral(true));
+        single_expr_eq("(real? 1+i)", Expression::BooleanLiteral(false));
+        single_expr_eq("(odd? -3)", Expression::BooleanLiteral(true));
+        single_expr_eq("(zero? 0.0)", Expression::BooleanLiteral(true));
+        single_expr_eq("(positive? 0)", Expression::BooleanLiteral(false));
+        single_expr_err("(quotient 1 0)");
+        single_expr_err("(quotient 7.5 2)");
+    }
+
+    #[test]
+    fn number_printing() {
+        single_expr_display("3.14159", "3.14159");
+        single_expr_display("1e300", "1e300");
+        single_expr_display("(/ 1. 3)", "0.3333333333333333");
+        single_expr_display("(* 1.0 100)", "100.0");
+        single_expr_display("(/ 1. 0.)", "+inf.0");
+        single_expr_display("(- (/ 1. 0.))", "-inf.0");
+        single_expr_display("(sqrt -2.25)", "+1.5i");
+        single_expr_display("#e1.1", "11/10");
+        single_expr_display("(number->string 255 16)", "\"ff\"");
+        single_expr_display("(number->string -5/3 2)", "\"-101/11\"");
+        single_expr_display("(number->string 0.1)", "\"0.1\"");
+        single_expr_eq("(string->number \"ff\" 16)", int_expr(255));
+        single_expr_eq("(string->number \"#o17\")", int_expr(15));
+        single_expr_eq(
+            "(string->number \"abc\")",
+            Expression::BooleanLiteral(false),
+        );
+        single_expr_eq(
+            "(nan? (string->number \"+nan.0\"))",
+            Expression::BooleanLiteral(true),
+        );
+        single_expr_err("(number->string 1.5 2)");
+    }
+
+    #[test]
+    fn oversized_exact_results_return_quickly() {
+        let start = Instant::now();
+        for input in [
+            "(expt 3 4000000)",
+            "(expt 1/3 4000000)",
+            "(expt 2 (expt 10 30))",
+        ] {
+            assert!(
+                matches!(
+                    single_expr_err(input).kind(),
+                    ErrorKind::ImplementationRestriction(_)
+                ),
+                "{}",
+                input
+            );
+        }
+        assert!(matches!(
+            single_expr_err("#e1e3000000").kind(),
+            ErrorKind::Syntax(_)
+        ));
+        single_expr_eq(
+            "(string->number (make-string 300000 #\\9))",
+            Expression::BooleanLiteral(false),
+        );
+        single_expr_display("(string-length (number->string (expt 2 70000) 2))", "70001");
+        single_expr_display("(exact? (expt 1/2 100000))", "#t");
+        single_expr_display("(expt -1 (expt 10 30))", "1");
+        single_expr_display("(string-length (number->string (expt 3 82000)))", "39124");
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        for input in [
+            "(/ 5 0)",
+            "(/ 0)",
+            "(/ 1/2 0)",
+            "(/ 1+i 0)",
+            "(quotient 1 0)",
+            "(modulo 1.0 0)",
+            "(expt 0 -1)",
+        ] {
+            assert!(
+                matches!(single_expr_err(input).kind(), ErrorKind::DivisionByZero),
+                "{}",
+                input
+            );
+        }
+        single_expr_display("(/ 1.0 0)", "+inf.0");
+        single_expr_display("(/ -1 0.)", "-inf.0");
+        single_expr_eq("(nan? (/ 0 0.))", Expression::BooleanLiteral(true));
+        single_expr_display("(/ 2)", "1/2");
+        single_expr_display("(/ -9223372036854775808 -1)", "9223372036854775808");
+        single_expr_display("(quotient -9223372036854775808 -1)", "9223372036854775808");
+        single_expr_eq(
+            "(string->number \"1/0\")",
+            Expression::BooleanLiteral(false),
+        );
+    }
+
+    #[test]
+    fn equivalence_predicates() {
+        let t = Expression::BooleanLiteral(true);
+        let f = Expression::BooleanLiteral(false);
+        single_expr_eq("(eq? 'a 'a)", t.clone());
+        single_expr_eq("(eq? '() '())", t.clone());
+        single_expr_eq("(eq? car car)", t.clone());
+        single_expr_eq("(eq? (list 1) (list 1))", f.clone());
+        single_expr_eq("(let ((x (list 1))) (eq? x x))", t.clone());
+        single_expr_eq("(let ((f (lambda () 1))) (eq? f f))", t.clone());
+        single_expr_eq("(eq? \"abc\" (string-copy \"abc\"))", f.clone());
+        single_expr_eq("(eqv? 2 2)", t.clone());
+        single_expr_eq("(eqv? 2 2.0)", f.clone());
+        single_expr_eq(
+            "(eqv? 100000000000000000000 100000000000000000000)",
+            t.clone(),
+        );
+        single_expr_eq("(eqv? 1/2 (/ 2 4))", t.clone());
+        single_expr_eq("(eqv? 0.0 -0.0)", f.clone());
+        single_expr_eq("(eqv? +nan.0 +nan.0)", t.clone());
+        single_expr_eq("(eqv? #\\a #\\a)", t.clone());
+        single_expr_eq("(eqv? (string #\\a) (string #\\a))", f.clone());
+        single_expr_eq(
+            "(equal? '(1 (2 #(3 \"x\"))) (list 1 (list 2 #(3 \"x\"))))",
+            t.clone(),
+        );
+        single_expr_eq("(equal? #u8(1 2) #u8(1 2))", t.clone());
+        single_expr_eq("(equal? '(1 2) '(1 2.0))", f.clone());
+        single_expr_eq("(equal? \"abc\" \"abd\")", f.clone());
+        single_expr_eq(
+            "(define a (list 1 2)) (set-cdr! (cdr a) a) \
+             (define b (list 1 2 1 2)) (set-cdr! (cdr (cdr (cdr b))) b) \
+             (equal? a b)",
+            t.clone(),
+        );
+        single_expr_eq(
+            "(define a (list 1 2)) (set-cdr! (cdr a) a) \
+             (define b (list 1 3)) (set-cdr! (cdr b) b) \
+             (equal? a b)",
+            f,
+        );
+        single_expr_eq(
+            "(define (iota n acc) (if (= n 0) acc (iota (- n 1) (cons n acc)))) \
+             (equal? (iota 1000 '()) (iota 1000 '()))",
+            t,
+        );
+    }
+
+    #[test]
+    fn bignum_promotion() {
+        single_expr_display("(* 99999999999 99999999999)", "9999999999800000000001");
+        single_expr_display(
+            "(define (fact n) (if (= n 0) 1 (* n (fact (- n 1))))) (fact 30)",
+            "265252859812191058636308480000000",
+        );
+        single_expr_eq(
+            "(- (+ 9223372036854775807 1) 1)",
+            int_expr(9223372036854775807),
+        );
+        single_expr_display("(- -9223372036854775808)", "9223372036854775808");
+        single_expr_display("(/ -9223372036854775808 -1)", "9223372036854775808");
+        single_expr_eq(
+            "(< 9223372036854775807 123456789012345678901234567890)",
+            Expression::BooleanLiteral(true),
+        );
+    }
+
     #[test]
     fn complex_arith() {
         single_expr_eq("(+ (* 3 (+ (* 2 4) (+ 3 5))) (+ (- 10 7) 6))", int_expr(57));
@@ -190,4 +1023,564 @@ mod test {
     fn literal_false() {
         single_expr_eq("#f", Expression::BooleanLiteral(false));
     }
+
+    #[test]
+    fn if_expression() {
+        single_expr_eq("(define x -3) (if (> x 0) x (- x))", int_expr(3));
+        single_expr_eq("(if #f 1)", Expression::Void);
+    }
+
+    #[test]
+    fn and_or_short_circuit() {
+        single_expr_eq("(and 1 2 3)", int_expr(3));
+        single_expr_eq("(and)", Expression::BooleanLiteral(true));
+        single_expr_eq(
+            "(and 1 #f undefined-symbol)",
+            Expression::BooleanLiteral(false),
+        );
+        single_expr_eq("(or #f 2 undefined-symbol)", int_expr(2));
+        single_expr_eq("(or)", Expression::BooleanLiteral(false));
+    }
+
+    #[test]
+    fn when_unless_begin() {
+        single_expr_eq("(when (> 2 1) 1 2 3)", int_expr(3));
+        single_expr_eq("(unless (> 2 1) 1)", Expression::Void);
+        single_expr_eq("(unless #f 1 2)", int_expr(2));
+        single_expr_eq("(begin (define y 4) (* y y))", int_expr(16));
+    }
+
+    #[test]
+    fn cond_clauses() {
+        single_expr_eq("(cond ((> 1 2) 1) (else 2 3))", int_expr(3));
+        single_expr_eq("(cond (#f 1) (5))", int_expr(5));
+        single_expr_eq("(cond ((+ 1 2) => (lambda (x) (* x x))))", int_expr(9));
+        single_expr_eq("(cond (#f 1))", Expression::Void);
+    }
+
+    #[test]
+    fn let_forms() {
+        single_expr_eq("(define x 10) (let ((x 1) (y x)) (+ x y))", int_expr(11));
+        single_expr_eq("(define x 10) (let* ((x 1) (y x)) (+ x y))", int_expr(2));
+        single_expr_eq(
+            "(letrec ((even? (lambda (n) (if (= n 0) #t (odd? (- n 1))))) \
+                      (odd? (lambda (n) (if (= n 0) #f (even? (- n 1)))))) \
+               (even? 10))",
+            Expression::BooleanLiteral(true),
+        );
+        single_expr_eq("(letrec* ((a 2) (b (* a 3))) b)", int_expr(6));
+        single_expr_eq("(let* ((x 1) (x (+ x 1))) x)", int_expr(2));
+        for input in [
+            "(let ((x 1) (x 2)) x)",
+            "(letrec ((f 1) (g 2) (f 3)) f)",
+            "(letrec* ((x 1) (x 2)) x)",
+            "(let loop ((i 0) (i 1)) i)",
+        ] {
+            assert!(
+                matches!(single_expr_err(input).kind(), ErrorKind::Syntax(_)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn named_let() {
+        single_expr_eq(
+            "(let loop ((i 0) (acc 1)) (if (= i 5) acc (loop (+ i 1) (* acc 2))))",
+            int_expr(32),
+        );
+    }
+
+    #[test]
+    fn internal_defines_and_multi_form_bodies() {
+        single_expr_eq(
+            "(define (f x) (define y (* x 2)) (define (g z) (+ y z)) (g 1)) (f 5)",
+            int_expr(11),
+        );
+        single_expr_eq("((lambda (x) (define y 3) (+ x y)) 4)", int_expr(7));
+        single_expr_eq(
+            "(define y 4) (define (f) (define y 1) y) (f) y",
+            int_expr(4),
+        );
+    }
+
+    #[test]
+    fn set_mutates_nearest_binding() {
+        single_expr_eq(
+            "(define (make-counter) (let ((n 0)) (lambda () (set! n (+ n 1)) n))) \
+             (define c (make-counter)) (c) (c) (c)",
+            int_expr(3),
+        );
+        single_expr_eq("(define x 1) (define (f) (set! x 2)) (f) x", int_expr(2));
+        single_expr_eq("(define x 1) (let ((x 5)) (set! x 6)) x", int_expr(1));
+    }
+
+    #[test]
+    fn set_unbound_variable() {
+        assert_eq!(
+            single_expr_err("(set! nothing 1)").kind().to_string(),
+            "Unbound variable 'nothing'"
+        );
+        assert!(matches!(
+            single_expr_err("(define (f) (set! y 1)) (f)").kind(),
+            ErrorKind::UnboundVariable(name) if name == "y"
+        ));
+    }
+
+    #[test]
+    fn pairs_and_lists() {
+        single_expr_display("(cons 1 2)", "(1 . 2)");
+        single_expr_display("(cons 1 (cons 2 (list)))", "(1 2)");
+        single_expr_display("(list 1 (list 2 3) (cons 4 5))", "(1 (2 3) (4 . 5))");
+        single_expr_display("(cons 1 (cons 2 3))", "(1 2 . 3)");
+        single_expr_display("(append (list 1 2) (list 3) 4)", "(1 2 3 . 4)");
+        single_expr_display("(reverse (list 1 2 3))", "(3 2 1)");
+        single_expr_eq("(car (cdr (list 1 2 3)))", int_expr(2));
+        single_expr_eq("(length (list 1 2 3))", int_expr(3));
+        single_expr_eq("(null? (cdr (list 1)))", Expression::BooleanLiteral(true));
+        single_expr_eq("(pair? (list))", Expression::BooleanLiteral(false));
+        single_expr_eq("(list? (cons 1 2))", Expression::BooleanLiteral(false));
+    }
+
+    #[test]
+    fn pair_mutation() {
+        single_expr_display(
+            "(define p (list 1 2)) (define q p) (set-car! p 3) (set-cdr! (cdr p) 4) q",
+            "(3 2 . 4)",
+        );
+        assert!(matches!(
+            single_expr_err("(car (list))").kind(),
+            ErrorKind::WrongType {
+                expected: "pair",
+                actual: Expression::EmptyList
+            }
+        ));
+    }
+
+    #[test]
+    fn circular_lists() {
+        let cycle = "(define x (list 1 2 3)) (set-cdr! (cdr (cdr x)) (cdr x)) ";
+        single_expr_eq(
+            &format!("{}(list? x)", cycle),
+            Expression::BooleanLiteral(false),
+        );
+        single_expr_display(&format!("{}x", cycle), "(1 . #0=(2 3 . #0#))");
+        for call in ["(length x)", "(reverse x)", "(append x '())"] {
+            let input = format!("{}{}", cycle, call);
+            assert!(
+                matches!(
+                    single_expr_err(&input).kind(),
+                    ErrorKind::WrongType {
+                        expected: "list",
+                        ..
+                    }
+                ),
+                "{}",
+                call
+            );
+        }
+        single_expr_display("(define x (list 1)) (set-car! x x) x", "#0=(#0#)");
+        single_expr_display("(define x (list 1)) (list x x)", "((1) (1))");
+    }
+
+    #[test]
+    fn long_and_deeply_nested_lists() {
+        let mut list = Expression::EmptyList;
+        for i in 0..1_000_000 {
+            list = Expression::cons(int_expr(i), list);
+        }
+        drop(list);
+
+        let depth = 100_000;
+        let mut nested = Expression::EmptyList;
+        for _ in 0..depth {
+            nested = Expression::cons(nested, Expression::EmptyList);
+        }
+        assert_eq!(
+            nested.to_string(),
+            format!("{}{}", "(".repeat(depth + 1), ")".repeat(depth + 1))
+        );
+    }
+
+    #[test]
+    fn quote_produces_data() {
+        single_expr_display("(quote (testing 1 (2) \"s\"))", "(testing 1 (2) \"s\")");
+        single_expr_display("'(a . b)", "(a . b)");
+        single_expr_display("'()", "()");
+        single_expr_display("(car ''a)", "quote");
+        single_expr_eq("(pair? '(1 2))", Expression::BooleanLiteral(true));
+        for input in ["'(1 . 2 3)", "'(1 .)", "'(1 . . 2)", "'(. 1)"] {
+            assert!(
+                matches!(single_expr_err(input).kind(), ErrorKind::Syntax(_)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn quasiquote_unquote() {
+        single_expr_display("(define x 5) `(x ,x ,(+ x 1))", "(x 5 6)");
+        single_expr_display("(define l '(1 2)) `(0 ,@l 3 ,@l)", "(0 1 2 3 1 2)");
+        single_expr_display("(define l '(1 2)) `(0 . ,l)", "(0 1 2)");
+        single_expr_display(
+            "`(1 `(2 ,(3 ,(+ 1 3))))",
+            "(1 (quasiquote (2 (unquote (3 4)))))",
+        );
+        single_expr_display("`#(1 ,(+ 1 1) ,@(list 3 4))", "#(1 2 3 4)");
+        single_expr_display("`(1 #(a ,(+ 1 1)))", "(1 #(a 2))");
+        assert!(matches!(
+            single_expr_err("`(1 . ,2 3)").kind(),
+            ErrorKind::Syntax(_)
+        ));
+        let err = single_expr_err("`(1\n  ,@2)");
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::WrongType {
+                expected: "list",
+                ..
+            }
+        ));
+        assert_eq!(err.span().map(|s| (s.line, s.column)), Some((2, 3)));
+        assert!(matches!(single_expr_err(",x").kind(), ErrorKind::Syntax(_)));
+    }
+
+    #[test]
+    fn symbols() {
+        single_expr_eq("(symbol? 'abc)", Expression::BooleanLiteral(true));
+        single_expr_eq("(symbol? \"abc\")", Expression::BooleanLiteral(false));
+        single_expr_eq("(symbol->string 'abc)", Expression::string("abc"));
+        single_expr_eq(
+            "(string->symbol \"abc\")",
+            Expression::Symbol(Symbol::new("abc")),
+        );
+        single_expr_display("(string->uninterned-symbol \"abc\")", "abc");
+        single_expr_eq("(symbol? (gensym))", Expression::BooleanLiteral(true));
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_space() {
+        single_expr_eq(
+            "(define (loop n) (cond ((= n 0) 0) (#t (loop (- n 1))))) (loop 100000)",
+            int_expr(0),
+        );
+        single_expr_eq(
+            "(define (count n acc) (if (= n 0) acc (begin (count (- n 1) (+ acc 1))))) \
+             (count 100000 0)",
+            int_expr(100000),
+        );
+        single_expr_eq(
+            "(let loop ((i 0)) (when (< i 100000) (let ((j (+ i 1))) (loop j))))",
+            Expression::Void,
+        );
+        single_expr_eq(
+            "(define (even? n) (or (= n 0) (odd? (- n 1)))) \
+             (define (odd? n) (and (> n 0) (even? (- n 1)))) \
+             (even? 100001)",
+            Expression::BooleanLiteral(false),
+        );
+    }
+
+    #[test]
+    fn structured_errors() {
+        assert!(matches!(
+            single_expr_err("(if)").kind(),
+            ErrorKind::Syntax(_)
+        ));
+        assert!(matches!(
+            single_expr_err("(+ 1 \"a\")").kind(),
+            ErrorKind::WrongType {
+                expected: "number",
+                ..
+            }
+        ));
+        assert!(matches!(
+            single_expr_err("(1 2)").kind(),
+            ErrorKind::WrongType {
+                expected: "procedure",
+                ..
+            }
+        ));
+        assert!(matches!(
+            single_expr_err("(cons 1)").kind(),
+            ErrorKind::Arity {
+                min: 2,
+                max: Some(2),
+                actual: 1,
+                ..
+            }
+        ));
+        assert!(matches!(
+            single_expr_err("((lambda (x) x))").kind(),
+            ErrorKind::Arity {
+                min: 1,
+                actual: 0,
+                ..
+            }
+        ));
+        assert_eq!(
+            single_expr_err("(error \"Something bad:\" 42 'foo)")
+                .kind()
+                .to_string(),
+            "Something bad: 42 foo"
+        );
+    }
+
+    #[test]
+    fn hash_literals() {
+        single_expr_eq("#true", Expression::BooleanLiteral(true));
+        single_expr_eq("(if #f 1 2)", int_expr(2));
+        assert!(matches!(
+            single_expr_err("(define #t 0)").kind(),
+            ErrorKind::Syntax(_)
+        ));
+        single_expr_eq("(char->integer #\\x41)", int_expr(65));
+        single_expr_display(
+            "(list #\\a #\\space #\\newline)",
+            "(#\\a #\\space #\\newline)",
+        );
+        single_expr_display("'#(1 a (b))", "#(1 a (b))");
+        single_expr_eq(
+            "(symbol? (vector-ref #(1 a) 1))",
+            Expression::BooleanLiteral(true),
+        );
+        single_expr_display("#u8(0 255)", "#u8(0 255)");
+        assert!(matches!(
+            single_expr_err("#u8(256)").kind(),
+            ErrorKind::Syntax(_)
+        ));
+    }
+
+    #[test]
+    fn strings() {
+        single_expr_display("\"a\\\"b\\n\"", "\"a\\\"b\\n\"");
+        single_expr_eq("(string-length \"h\u{e9}llo\")", int_expr(5));
+        single_expr_eq("(string-ref \"abc\" 1)", Expression::CharLiteral('b'));
+        single_expr_display("(substring \"hello\" 1 3)", "\"el\"");
+        single_expr_display("(string-append \"a\" \"b\" \"c\")", "\"abc\"");
+        single_expr_display("(make-string 3 #\\a)", "\"aaa\"");
+        assert!(matches!(
+            single_expr_err("(make-string 100000000000000)").kind(),
+            ErrorKind::ImplementationRestriction(_)
+        ));
+        single_expr_display(
+            "(define s (string-copy \"abc\")) (string-set! s 1 #\\x) s",
+            "\"axc\"",
+        );
+        single_expr_display(
+            "(define (f) \"abc\") (string-set! (f) 0 #\\x) (f)",
+            "\"abc\"",
+        );
+        single_expr_display(
+            "(define (f) '(\"abc\")) (string-set! (car (f)) 0 #\\x) (f)",
+            "(\"abc\")",
+        );
+        single_expr_eq(
+            "(string<? \"abc\" \"abd\" \"b\")",
+            Expression::BooleanLiteral(true),
+        );
+        single_expr_eq("(string=? \"a\" \"A\")", Expression::BooleanLiteral(false));
+        single_expr_eq(
+            "(string-ci=? \"a\" \"A\")",
+            Expression::BooleanLiteral(true),
+        );
+        single_expr_display("(string-upcase \"abc\")", "\"ABC\"");
+        single_expr_display("(string->list \"abc\" 1)", "(#\\b #\\c)");
+        single_expr_display("(string-split \"a,b,,c\" #\\,)", "(\"a\" \"b\" \"\" \"c\")");
+        single_expr_display("(string-join '(\"a\" \"b\") \", \")", "\"a, b\"");
+        single_expr_eq("(string-index \"hello\" #\\l)", int_expr(2));
+        single_expr_eq(
+            "(string-index \"hello\" (lambda (c) (char? c)) 3)",
+            int_expr(3),
+        );
+        single_expr_eq(
+            "(string-index \"abc\" #\\z)",
+            Expression::BooleanLiteral(false),
+        );
+        assert!(matches!(
+            single_expr_err("(substring \"abc\" 2 1)").kind(),
+            ErrorKind::WrongType { .. }
+        ));
+    }
+
+    #[test]
+    fn datum_comments() {
+        single_expr_eq("(+ 1 #;(* 2 3) 4) #;5", int_expr(5));
+        single_expr_eq("(list #;#;1 2 3)", Expression::list(vec![int_expr(3)]));
+    }
+
+    #[test]
+    fn exit_is_raised_as_error() {
+        assert!(matches!(
+            single_expr_err("(define (f) (exit 3)) (f) 4").kind(),
+            ErrorKind::Exit(3)
+        ));
+        assert!(matches!(
+            single_expr_err("(exit #f)").kind(),
+            ErrorKind::Exit(1)
+        ));
+        for status in &["256", "-1", "4294967296"] {
+            assert!(matches!(
+                single_expr_err(&format!("(exit {})", status)).kind(),
+                ErrorKind::WrongType { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn errors_carry_source_location() {
+        let source = "(define (f x)\n  (+ x (car x)))\n(f '())";
+        let err = single_expr_err(source);
+        let span = err.span().unwrap();
+        assert_eq!((span.line, span.column), (2, 8));
+        assert_eq!(
+            err.report(source, span.source),
+            "Expecting pair, got '()' at line 2, column 8\n\
+             2 |   (+ x (car x)))\n  |        ^^^^^^^\n\
+             Backtrace:\n  (f ()) at line 3, column 1"
+        );
+
+        let err = single_expr_err("(+ 1\n  (* 2 3)");
+        assert_eq!(err.span().map(|s| (s.line, s.column)), Some((1, 1)));
+
+        let err = single_expr_err("undefined-thing");
+        assert_eq!(err.span().map(|s| (s.line, s.column)), Some((1, 1)));
+        let err = single_expr_err("(+ 1\n   foo)");
+        assert_eq!(err.span().map(|s| (s.line, s.column)), Some((2, 4)));
+    }
+
+    #[test]
+    fn errors_are_only_excerpted_against_their_own_source() {
+        let env = create_root_environment();
+        for expr in parse("(define (f x)\n  (+ x (car x)))", SourceId::fresh()).unwrap() {
+            eval(&expr, &env).unwrap();
+        }
+        let (source, id) = (
+            "\"\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\" (f 1)",
+            SourceId::fresh(),
+        );
+        let err = parse(source, id)
+            .unwrap()
+            .iter()
+            .map(|expr| eval(expr, &env))
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(
+            err.report(source, id),
+            "Expecting pair, got '1' at line 2, column 8\n\
+             Backtrace:\n  (f 1) at line 1, column 10"
+        );
+    }
+
+    #[test]
+    fn errors_carry_backtrace() {
+        let source = "(define (inner x) (car x))\n\
+                      (define (middle x) (+ 1 (inner x)))\n\
+                      (define (outer x) (* 2 (middle x)))\n\
+                      (outer 5)";
+        let err = single_expr_err(source);
+        let frames: Vec<String> = err.backtrace().iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            frames,
+            vec![
+                "(inner 5) at line 2, column 25",
+                "(middle 5) at line 3, column 24",
+                "(outer 5) at line 4, column 1",
+            ]
+        );
+        assert!(err.report(source, err.span().unwrap().source).ends_with(
+            "Backtrace:\n  (inner 5) at line 2, column 25\n  \
+             (middle 5) at line 3, column 24\n  (outer 5) at line 4, column 1"
+        ));
+    }
+
+    #[test]
+    fn deep_recursion_is_an_error() {
+        let interpreter = std::thread::Builder::new()
+            .stack_size(EVAL_STACK_SIZE)
+            .spawn(|| {
+                let define = "(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1))))) ";
+                single_expr_eq(&format!("{}(f 5000)", define), int_expr(5000));
+                let source = format!("{}(f 100000)", define);
+                let err = single_expr_err(&source);
+                assert!(matches!(
+                    err.kind(),
+                    ErrorKind::ImplementationRestriction(_)
+                ));
+                let report = err.report(&source, err.span().unwrap().source);
+                assert_eq!(report.lines().count(), 25);
+                assert!(report.lines().last().unwrap().starts_with("  ... and "));
+            })
+            .unwrap();
+        interpreter.join().unwrap();
+    }
+
+    #[test]
+    fn backtrace_omits_completed_tail_calls() {
+        let err = single_expr_err(
+            "(define (f n) (if (= n 0) (car n) (f (- n 1)))) (define (g) (f 3) 1) (g)",
+        );
+        let names: Vec<Option<String>> = err
+            .backtrace()
+            .iter()
+            .map(|f| f.procedure.clone())
+            .collect();
+        assert_eq!(names, vec![Some("f".to_string()), Some("g".to_string())]);
+        assert_eq!(err.backtrace()[0].arguments, vec![int_expr(0)]);
+    }
+
+    #[test]
+    fn lambda_application() {
+        single_expr_eq("((lambda (x y) (+ x y)) 3 4)", int_expr(7));
+    }
+
+    #[test]
+    fn rest_parameters() {
+        single_expr_display("((lambda (a . b) b) 1 2 3)", "(2 3)");
+        single_expr_display("((lambda args args) 1 2)", "(1 2)");
+        single_expr_display("((lambda args args))", "()");
+        single_expr_display("(define (f a . rest) (list a rest)) (f 1)", "(1 ())");
+        single_expr_display("(define (g . rest) rest) (g 1 2)", "(1 2)");
+        assert!(matches!(
+            single_expr_err("((lambda (a . b) b))").kind(),
+            ErrorKind::Arity {
+                min: 1,
+                max: None,
+                actual: 0,
+                ..
+            }
+        ));
+        for input in [
+            "(lambda (x x) x)",
+            "(define (f a a) a)",
+            "(lambda (a . b c) a)",
+            "(lambda (a .) a)",
+            "(lambda (. a) a)",
+            "(define (. a) a)",
+            "(lambda . a)",
+        ] {
+            assert!(
+                matches!(single_expr_err(input).kind(), ErrorKind::Syntax(_)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn closure_captures_definition_environment() {
+        single_expr_eq(
+            "(define (make-adder n) (lambda (x) (+ x n))) (define add5 (make-adder 5)) (add5 10)",
+            int_expr(15),
+        );
+    }
+
+    #[test]
+    fn lexical_not_dynamic_scope() {
+        single_expr_eq(
+            "(define x 1) (define (get-x) x) (define (shadow x) (get-x)) (shadow 2)",
+            int_expr(1),
+        );
+    }
 }
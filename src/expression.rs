@@ -1,32 +1,420 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::rc::Rc;
 
+use crate::environment::Environment;
+use crate::error::SchemeError;
 use crate::number::Number;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+/// A procedure defined in Scheme code, closing over its defining environment.
+pub struct Lambda {
+    pub name: Option<Symbol>,
+    pub params: Vec<Symbol>,
+    /// The parameter bound to a list of the arguments after `params`, if
+    /// the procedure accepts any number of them.
+    pub rest: Option<Symbol>,
+    pub body: Vec<Expression>,
+    pub env: Environment,
+}
+
+pub struct Pair {
+    pub car: Expression,
+    pub cdr: Expression,
+}
+
+/// Frees the pairs and vectors a pair owns with an explicit stack, since the
+/// default recursive drop overflows on long lists.
+impl Drop for Pair {
+    fn drop(&mut self) {
+        let mut stack = vec![
+            std::mem::replace(&mut self.cdr, Expression::Void),
+            std::mem::replace(&mut self.car, Expression::Void),
+        ];
+        while let Some(expr) = stack.pop() {
+            match expr {
+                Expression::Pair(pair) => {
+                    if let Ok(pair) = Rc::try_unwrap(pair) {
+                        let mut pair = pair.into_inner();
+                        stack.push(std::mem::replace(&mut pair.cdr, Expression::Void));
+                        stack.push(std::mem::replace(&mut pair.car, Expression::Void));
+                    }
+                }
+                Expression::Vector(elements) => {
+                    if let Ok(elements) = Rc::try_unwrap(elements) {
+                        stack.extend(elements.into_inner());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Characters written by name, as in `#\\space`.
+pub const CHARACTER_NAMES: &[(&str, char)] = &[
+    ("alarm", '\u{7}'),
+    ("backspace", '\u{8}'),
+    ("delete", '\u{7f}'),
+    ("escape", '\u{1b}'),
+    ("newline", '\n'),
+    ("null", '\0'),
+    ("return", '\r'),
+    ("space", ' '),
+    ("tab", '\t'),
+];
+
+/// Writes `s` as a string literal, escaping characters as needed for it to
+/// be read back.
+pub(crate) fn write_string_literal(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            c if c.is_control() => write!(f, "\\x{:x};", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
 
 #[derive(Clone)]
 pub enum Expression {
-    Combination(Vec<Expression>),
-    Identifier(String),
-    StringLiteral(String),
+    Combination(Rc<[Expression]>, Span),
+    Identifier(Symbol, Span),
+    StringLiteral(Rc<RefCell<String>>),
     NumberLiteral(Number),
     BooleanLiteral(bool),
-    Procedure(Vec<String>, Box<Expression>),
-    BuiltinProcedure(Rc<dyn Fn(Vec<Expression>) -> Result<Expression, String>>),
+    CharLiteral(char),
+    Symbol(Symbol),
+    Pair(Rc<RefCell<Pair>>),
+    EmptyList,
+    Vector(Rc<RefCell<Vec<Expression>>>),
+    Bytevector(Rc<RefCell<Vec<u8>>>),
+    Procedure(Rc<Lambda>),
+    BuiltinProcedure(Rc<dyn Fn(Vec<Expression>) -> Result<Expression, SchemeError>>),
     Void,
 }
 
+impl Expression {
+    /// Returns a new mutable string.
+    pub fn string<S: Into<String>>(s: S) -> Expression {
+        Expression::StringLiteral(Rc::new(RefCell::new(s.into())))
+    }
+
+    pub fn cons(car: Expression, cdr: Expression) -> Expression {
+        Expression::Pair(Rc::new(RefCell::new(Pair { car, cdr })))
+    }
+
+    /// Builds a proper list from the elements, ending in the empty list.
+    pub fn list<I>(elements: I) -> Expression
+    where
+        I: IntoIterator<Item = Expression>,
+        I::IntoIter: DoubleEndedIterator,
+    {
+        elements
+            .into_iter()
+            .rev()
+            .fold(Expression::EmptyList, |tail, e| Expression::cons(e, tail))
+    }
+
+    /// Whether both are the same object, as decided by `eq?`. Fixnums,
+    /// floats, characters, booleans and symbols are compared by value, and
+    /// everything else by identity.
+    pub fn is_eq(&self, other: &Expression) -> bool {
+        match (self, other) {
+            (Expression::NumberLiteral(n1), Expression::NumberLiteral(n2)) => match (n1, n2) {
+                (Number::Big(a), Number::Big(b)) => Rc::ptr_eq(a, b),
+                (Number::Rational(a), Number::Rational(b)) => Rc::ptr_eq(a, b),
+                (Number::Complex(a), Number::Complex(b)) => Rc::ptr_eq(a, b),
+                _ => n1.is_eqv(n2),
+            },
+            (Expression::Combination(c1, _), Expression::Combination(c2, _)) => Rc::ptr_eq(c1, c2),
+            (Expression::Identifier(s1, _), Expression::Identifier(s2, _)) => s1 == s2,
+            (Expression::StringLiteral(s1), Expression::StringLiteral(s2)) => Rc::ptr_eq(s1, s2),
+            (Expression::BooleanLiteral(b1), Expression::BooleanLiteral(b2)) => b1 == b2,
+            (Expression::CharLiteral(c1), Expression::CharLiteral(c2)) => c1 == c2,
+            (Expression::Symbol(s1), Expression::Symbol(s2)) => s1 == s2,
+            (Expression::Pair(p1), Expression::Pair(p2)) => Rc::ptr_eq(p1, p2),
+            (Expression::EmptyList, Expression::EmptyList) => true,
+            (Expression::Vector(v1), Expression::Vector(v2)) => Rc::ptr_eq(v1, v2),
+            (Expression::Bytevector(b1), Expression::Bytevector(b2)) => Rc::ptr_eq(b1, b2),
+            (Expression::Procedure(p1), Expression::Procedure(p2)) => Rc::ptr_eq(p1, p2),
+            (Expression::BuiltinProcedure(p1), Expression::BuiltinProcedure(p2)) => {
+                std::ptr::addr_eq(Rc::as_ptr(p1), Rc::as_ptr(p2))
+            }
+            (Expression::Void, Expression::Void) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether both are equivalent as decided by `eqv?`, which is like `eq?`
+    /// except that numbers are compared with `Number::is_eqv`.
+    pub fn is_eqv(&self, other: &Expression) -> bool {
+        match (self, other) {
+            (Expression::NumberLiteral(n1), Expression::NumberLiteral(n2)) => n1.is_eqv(n2),
+            _ => self.is_eq(other),
+        }
+    }
+
+    /// Whether both have the same structure, as decided by `equal?`. Pairs,
+    /// vectors, strings and bytevectors are compared by their contents, and
+    /// everything else with `eqv?`. Cyclic structures are handled.
+    pub fn is_equal(&self, other: &Expression) -> bool {
+        // Containers already being compared are assumed to be equal when they
+        // are reached again, which ends the walk around a cycle.
+        let mut seen = HashSet::new();
+        let mut pending = vec![(self.clone(), other.clone())];
+        while let Some((e1, e2)) = pending.pop() {
+            match (&e1, &e2) {
+                (Expression::Pair(p1), Expression::Pair(p2)) => {
+                    if seen.insert((Rc::as_ptr(p1) as usize, Rc::as_ptr(p2) as usize)) {
+                        let (p1, p2) = (p1.borrow(), p2.borrow());
+                        pending.push((p1.cdr.clone(), p2.cdr.clone()));
+                        pending.push((p1.car.clone(), p2.car.clone()));
+                    }
+                }
+                (Expression::Vector(v1), Expression::Vector(v2)) => {
+                    if seen.insert((Rc::as_ptr(v1) as usize, Rc::as_ptr(v2) as usize)) {
+                        let (v1, v2) = (v1.borrow(), v2.borrow());
+                        if v1.len() != v2.len() {
+                            return false;
+                        }
+                        pending.extend(v1.iter().cloned().zip(v2.iter().cloned()).rev());
+                    }
+                }
+                (Expression::Combination(c1, _), Expression::Combination(c2, _)) => {
+                    if c1.len() != c2.len() {
+                        return false;
+                    }
+                    pending.extend(c1.iter().cloned().zip(c2.iter().cloned()).rev());
+                }
+                (Expression::StringLiteral(s1), Expression::StringLiteral(s2)) => {
+                    if *s1.borrow() != *s2.borrow() {
+                        return false;
+                    }
+                }
+                (Expression::Bytevector(b1), Expression::Bytevector(b2)) => {
+                    if *b1.borrow() != *b2.borrow() {
+                        return false;
+                    }
+                }
+                _ => {
+                    if !e1.is_eqv(&e2) {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns the elements of a proper list, or `None` for anything else.
+    /// Circular lists are not proper lists and are detected by a second
+    /// pointer that advances at half the speed.
+    pub fn list_to_vec(&self) -> Option<Vec<Expression>> {
+        let mut elements = Vec::new();
+        let mut current = self.clone();
+        let mut slow = self.clone();
+        loop {
+            current = match current {
+                Expression::EmptyList => return Some(elements),
+                Expression::Pair(pair) => {
+                    let pair = pair.borrow();
+                    elements.push(pair.car.clone());
+                    pair.cdr.clone()
+                }
+                _ => return None,
+            };
+            if elements.len() % 2 == 0 {
+                slow = match slow {
+                    Expression::Pair(pair) => pair.borrow().cdr.clone(),
+                    _ => unreachable!("the slow pointer trails a list"),
+                };
+                if let (Expression::Pair(p1), Expression::Pair(p2)) = (&current, &slow) {
+                    if Rc::ptr_eq(p1, p2) {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Identifies a pair or vector by its allocation.
+    fn container_id(&self) -> Option<usize> {
+        match self {
+            Expression::Pair(pair) => Some(Rc::as_ptr(pair) as usize),
+            Expression::Vector(elements) => Some(Rc::as_ptr(elements) as usize),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the pairs and vectors reachable from `expr` that contain
+/// themselves. Every cycle passes through at least one of them.
+fn find_cycles(expr: &Expression) -> HashSet<usize> {
+    enum Visit {
+        Enter(Expression),
+        Leave(usize),
+    }
+    let mut cycles = HashSet::new();
+    // The containers being visited, and those already finished with.
+    let mut path = HashSet::new();
+    let mut done = HashSet::new();
+    let mut stack = vec![Visit::Enter(expr.clone())];
+    while let Some(visit) = stack.pop() {
+        let expr = match visit {
+            Visit::Enter(expr) => expr,
+            Visit::Leave(id) => {
+                path.remove(&id);
+                done.insert(id);
+                continue;
+            }
+        };
+        let id = match expr.container_id() {
+            Some(id) => id,
+            None => continue,
+        };
+        if path.contains(&id) {
+            cycles.insert(id);
+            continue;
+        }
+        if done.contains(&id) {
+            continue;
+        }
+        path.insert(id);
+        stack.push(Visit::Leave(id));
+        match &expr {
+            Expression::Pair(pair) => {
+                let pair = pair.borrow();
+                stack.push(Visit::Enter(pair.cdr.clone()));
+                stack.push(Visit::Enter(pair.car.clone()));
+            }
+            Expression::Vector(elements) => {
+                let elements = elements.borrow();
+                stack.extend(elements.iter().rev().cloned().map(Visit::Enter));
+            }
+            _ => unreachable!("only pairs and vectors have ids"),
+        }
+    }
+    cycles
+}
+
+/// Writes pairs and vectors, labelling those that contain themselves as in
+/// `#0=(1 . #0#)` so that printing terminates.
+struct CyclePrinter {
+    cycles: HashSet<usize>,
+    labels: HashMap<usize, usize>,
+}
+
+/// Pending output of a `CyclePrinter`, kept on an explicit stack so that
+/// deeply nested data cannot overflow the native one.
+enum Print {
+    Datum(Expression),
+    /// The rest of a list after its first element.
+    Tail(Expression),
+    Text(&'static str),
+}
+
+impl CyclePrinter {
+    fn write(&mut self, f: &mut fmt::Formatter<'_>, expr: &Expression) -> fmt::Result {
+        let mut stack = vec![Print::Datum(expr.clone())];
+        while let Some(print) = stack.pop() {
+            match print {
+                Print::Text(text) => write!(f, "{}", text)?,
+                Print::Datum(expr) => self.write_datum(f, expr, &mut stack)?,
+                Print::Tail(Expression::EmptyList) => {}
+                Print::Tail(Expression::Pair(next))
+                    if !self.cycles.contains(&(Rc::as_ptr(&next) as usize)) =>
+                {
+                    let next = next.borrow();
+                    write!(f, " ")?;
+                    stack.push(Print::Tail(next.cdr.clone()));
+                    stack.push(Print::Datum(next.car.clone()));
+                }
+                Print::Tail(other) => {
+                    write!(f, " . ")?;
+                    stack.push(Print::Datum(other));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the start of `expr`, pushing what remains onto `stack`.
+    fn write_datum(
+        &mut self,
+        f: &mut fmt::Formatter<'_>,
+        expr: Expression,
+        stack: &mut Vec<Print>,
+    ) -> fmt::Result {
+        if let Some(id) = expr.container_id().filter(|id| self.cycles.contains(id)) {
+            if let Some(label) = self.labels.get(&id) {
+                return write!(f, "#{}#", label);
+            }
+            let label = self.labels.len();
+            self.labels.insert(id, label);
+            write!(f, "#{}=", label)?;
+        }
+        match expr {
+            Expression::Pair(pair) => {
+                let pair = pair.borrow();
+                stack.push(Print::Text(")"));
+                stack.push(Print::Tail(pair.cdr.clone()));
+                stack.push(Print::Datum(pair.car.clone()));
+                write!(f, "(")
+            }
+            Expression::Vector(elements) => {
+                stack.push(Print::Text(")"));
+                for (i, element) in elements.borrow().iter().enumerate().rev() {
+                    stack.push(Print::Datum(element.clone()));
+                    if i > 0 {
+                        stack.push(Print::Text(" "));
+                    }
+                }
+                write!(f, "#(")
+            }
+            other => write!(f, "{}", other),
+        }
+    }
+}
+
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Expression::Combination(elements) => {
+            Expression::Combination(elements, _) => {
                 let sub: Vec<String> = elements.iter().map(|e| format!("{}", e)).collect();
                 write!(f, "({})", sub.join(" "))
             }
-            Expression::Identifier(id) => write!(f, "{}", id),
-            Expression::StringLiteral(s) => write!(f, "\"{}\"", s),
+            Expression::Identifier(id, _) => write!(f, "{}", id),
+            Expression::StringLiteral(s) => write_string_literal(f, &s.borrow()),
+            Expression::Symbol(s) => write!(f, "{}", s),
             Expression::NumberLiteral(v) => write!(f, "{}", v),
             Expression::BooleanLiteral(b) => write!(f, "#{}", if *b { "t" } else { "f" }),
-            Expression::Procedure(_, _) => write!(f, "#procedure"),
+            Expression::CharLiteral(c) => {
+                match CHARACTER_NAMES.iter().find(|(_, named)| named == c) {
+                    Some((name, _)) => write!(f, "#\\{}", name),
+                    None if c.is_control() => write!(f, "#\\x{:x}", *c as u32),
+                    None => write!(f, "#\\{}", c),
+                }
+            }
+            Expression::Pair(_) | Expression::Vector(_) => CyclePrinter {
+                cycles: find_cycles(self),
+                labels: HashMap::new(),
+            }
+            .write(f, self),
+            Expression::EmptyList => write!(f, "()"),
+            Expression::Bytevector(bytes) => {
+                let sub: Vec<String> = bytes.borrow().iter().map(|b| format!("{}", b)).collect();
+                write!(f, "#u8({})", sub.join(" "))
+            }
+            Expression::Procedure(_) => write!(f, "#procedure"),
             Expression::BuiltinProcedure(_) => write!(f, "#builtin"),
             Expression::Void => write!(f, ""),
         }
@@ -39,16 +427,9 @@ impl fmt::Debug for Expression {
     }
 }
 
+/// Compares expressions as `equal?` does.
 impl PartialEq for Expression {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Expression::Combination(c1), Expression::Combination(c2)) => c1 == c2,
-            (Expression::Identifier(i1), Expression::Identifier(i2)) => i1 == i2,
-            (Expression::StringLiteral(s1), Expression::StringLiteral(s2)) => s1 == s2,
-            (Expression::NumberLiteral(n1), Expression::NumberLiteral(n2)) => n1 == n2,
-            (Expression::BooleanLiteral(b1), Expression::BooleanLiteral(b2)) => b1 == b2,
-            (Expression::Void, Expression::Void) => true,
-            _ => false
-        }
+        self.is_equal(other)
     }
-}
\ No newline at end of file
+}
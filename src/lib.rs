@@ -1,6 +1,11 @@
+pub mod bigint;
 pub mod number;
+pub mod rational;
+pub mod symbol;
 pub mod expression;
 pub mod tokenizer;
 pub mod parser;
+pub mod span;
 pub mod environment;
+pub mod error;
 pub mod eval;
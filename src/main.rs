@@ -1,26 +1,342 @@
-use simple_scheme_interpreter::{environment::create_root_environment, eval::eval, parser::Parser, tokenizer::tokenize};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::PathBuf;
 
-fn main() {
-    let input = "(define (abs x) (cond ((> x 0) x) ((= x 0) 0) ((< x 0) (- x))))\n(abs -4)\n(abs 0)\n(abs 4)";
-    let tokens = tokenize(input.chars());
+use std::process;
+use std::thread;
+
+use rustyline::{error::ReadlineError, Config, DefaultEditor};
+
+use simple_scheme_interpreter::{
+    environment::{create_root_environment, define_command_line, Environment},
+    error::{ErrorKind, SchemeError},
+    eval::{eval, EVAL_STACK_SIZE},
+    expression::Expression,
+    parser::{parse, Parser},
+    span::SourceId,
+    tokenizer::tokenize,
+};
 
-    let mut env = create_root_environment();
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+const HISTORY_FILE: &str = ".simple_scheme_history";
+const HISTORY_LIMIT: usize = 1000;
+const USAGE: &str = "Usage: scheme [-l FILE]... [-e EXPR]... [SCRIPT [ARG]...]
 
-    for expr in Parser::new(tokens) {
-        match expr {
-            Ok(ex) => match eval(&ex, &mut env) {
-                Ok(value) => {
-                    println!("{}", value);
+Runs SCRIPT with the given arguments, or evaluates each EXPR and prints its
+value. Without SCRIPT or EXPR, starts an interactive session.
+
+Options:
+  -l, --load FILE   Load FILE before anything else
+  -e, --eval EXPR   Evaluate EXPR and print its value
+  -h, --help        Print this help";
+
+/// What to run, as given on the command line.
+#[derive(Default)]
+struct Options {
+    load: Vec<String>,
+    eval: Vec<String>,
+    /// The script and the arguments passed to it.
+    script: Vec<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-l" | "--load" => options
+                    .load
+                    .push(args.next().ok_or("missing file after -l")?),
+                "-e" | "--eval" => options
+                    .eval
+                    .push(args.next().ok_or("missing expression after -e")?),
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
                 }
-                Err(err) => {
-                    println!("Error: {}", err);
+                "--" => {
+                    options.script.extend(args);
                     break;
                 }
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    return Err(format!("unknown option '{}'\n\n{}", arg, USAGE));
+                }
+                _ => {
+                    options.script.push(arg);
+                    options.script.extend(args);
+                    break;
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Where the REPL reads its input from: a line editor with history recall,
+/// persisted in a dotfile in the user's home directory, when stdin is a
+/// terminal, and plain lines otherwise.
+enum Lines {
+    Editor {
+        editor: Box<DefaultEditor>,
+        history: Option<PathBuf>,
+    },
+    Stdin(io::Lines<io::StdinLock<'static>>),
+}
+
+impl Lines {
+    fn new() -> Self {
+        if io::stdin().is_terminal() {
+            let config = Config::builder()
+                .max_history_size(HISTORY_LIMIT)
+                .map(|builder| builder.build());
+            match config.and_then(DefaultEditor::with_config) {
+                Ok(mut editor) => {
+                    let history =
+                        env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+                    if let Some(path) = &history {
+                        let _ = editor.load_history(path);
+                    }
+                    return Lines::Editor {
+                        editor: Box::new(editor),
+                        history,
+                    };
+                }
+                Err(err) => eprintln!("Warning: could not start line editor: {}", err),
+            }
+        }
+        Lines::Stdin(io::stdin().lock().lines())
+    }
+
+    fn is_interactive(&self) -> bool {
+        matches!(self, Lines::Editor { .. })
+    }
+
+    /// Reads the next line, showing `prompt` if interactive. Returns `None`
+    /// at end of input.
+    fn read(&mut self, prompt: &str) -> Result<Option<String>, ReadlineError> {
+        match self {
+            Lines::Editor { editor, .. } => match editor.readline(prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        editor.add_history_entry(line.as_str())?;
+                    }
+                    Ok(Some(line))
+                }
+                Err(ReadlineError::Eof) => Ok(None),
+                Err(err) => Err(err),
             },
+            Lines::Stdin(lines) => Ok(lines.next().transpose()?),
+        }
+    }
+
+    /// Writes the most recent lines back to the history file.
+    fn save_history(&mut self) {
+        if let Lines::Editor {
+            editor,
+            history: Some(path),
+        } = self
+        {
+            if let Err(err) = editor.save_history(path) {
+                eprintln!("Warning: could not write history: {}", err);
+            }
+        }
+    }
+}
+
+/// The outcome of evaluating a piece of REPL input.
+enum Input {
+    /// The input ends in the middle of a datum and more lines are needed.
+    Incomplete,
+    Evaluated {
+        failed: bool,
+    },
+    Exit(i32),
+}
+
+/// Evaluates every datum in `input`, printing values and errors. Nothing is
+/// evaluated if `input` ends in the middle of a datum, so the caller can read
+/// more lines.
+fn eval_input(input: &str, env: &Environment) -> Input {
+    let id = SourceId::fresh();
+    let exprs: Vec<_> = Parser::new(tokenize(input.chars(), id)).collect();
+    if let Some(Err(err)) = exprs.last() {
+        if let ErrorKind::Incomplete(_) = err.kind() {
+            return Input::Incomplete;
+        }
+    }
+    let mut failed = false;
+    for expr in exprs {
+        match expr.and_then(|ex| eval(&ex, env)) {
+            Ok(Expression::Void) => {}
+            Ok(value) => println!("{}", value),
             Err(err) => {
-                println!("Error: {}", err);
+                if let ErrorKind::Exit(status) = err.kind() {
+                    return Input::Exit(*status);
+                }
+                println!("Error: {}", err.report(input, id));
+                failed = true;
+            }
+        }
+    }
+    Input::Evaluated { failed }
+}
+
+/// Reads and evaluates lines from stdin until end of input or `(exit)`,
+/// returning the exit status. When stdin is not a terminal, the status is
+/// non-zero if any evaluation failed.
+fn repl(env: &Environment) -> i32 {
+    let mut lines = Lines::new();
+    let interactive = lines.is_interactive();
+
+    let mut status = 0;
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        let line = match lines.read(prompt) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(ReadlineError::Interrupted) => {
+                // Ctrl-C discards the pending input.
+                input.clear();
+                continue;
+            }
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                status = 1;
                 break;
             }
+        };
+        input.push_str(&line);
+        input.push('\n');
+        match eval_input(&input, env) {
+            Input::Incomplete => continue,
+            Input::Evaluated { failed } => {
+                if failed && !interactive {
+                    status = 1;
+                }
+            }
+            Input::Exit(code) => {
+                status = code;
+                input.clear();
+                break;
+            }
+        }
+        input.clear();
+    }
+    if !input.trim().is_empty() {
+        // Report the incomplete datum left at end of input.
+        let id = SourceId::fresh();
+        for expr in Parser::new(tokenize(input.chars(), id)) {
+            if let Err(err) = expr {
+                println!("Error: {}", err.report(&input, id));
+                status = 1;
+            }
+        }
+    }
+    if interactive {
+        lines.save_history();
+        println!();
+    }
+    status
+}
+
+/// Evaluates every datum in `source`, stopping at the first error. Nothing is
+/// evaluated if `source` has syntax errors, all of which are returned. Values
+/// are printed if `print` is set.
+fn run(source: &str, id: SourceId, env: &Environment, print: bool) -> Result<(), Vec<SchemeError>> {
+    for expr in parse(source, id)? {
+        let value = eval(&expr, env).map_err(|err| vec![err])?;
+        if print && value != Expression::Void {
+            println!("{}", value);
+        }
+    }
+    Ok(())
+}
+
+/// Runs a file, reporting any error against its contents. Returns the exit
+/// status if the program should end.
+fn run_file(path: &str, env: &Environment) -> Option<i32> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("{}: {}", path, SchemeError::from(err));
+            return Some(1);
+        }
+    };
+    let id = SourceId::fresh();
+    report(path, &source, id, run(&source, id, env, false))
+}
+
+/// Reports the outcome of running `source`, returning the exit status if the
+/// program should end.
+fn report(
+    origin: &str,
+    source: &str,
+    id: SourceId,
+    result: Result<(), Vec<SchemeError>>,
+) -> Option<i32> {
+    let errors = result.err()?;
+    for err in &errors {
+        match err.kind() {
+            ErrorKind::Exit(status) => return Some(*status),
+            _ => eprintln!("{}: Error: {}", origin, err.report(source, id)),
+        }
+    }
+    Some(1)
+}
+
+fn main() {
+    // Evaluation recurses on the native stack, so run it on a thread with
+    // room for the deepest nesting the evaluator allows.
+    let interpreter = thread::Builder::new()
+        .stack_size(EVAL_STACK_SIZE)
+        .spawn(interpret)
+        .expect("could not start the interpreter thread");
+    if interpreter.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn interpret() {
+    let mut args = env::args();
+    let program = args.next().unwrap_or_else(|| "scheme".to_string());
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        }
+    };
+
+    let env = create_root_environment();
+    let mut command_line = options.script.clone();
+    if command_line.is_empty() {
+        command_line.push(program);
+    }
+    define_command_line(&env, command_line);
+
+    for path in &options.load {
+        if let Some(status) = run_file(path, &env) {
+            process::exit(status);
+        }
+    }
+    for expr in &options.eval {
+        let id = SourceId::fresh();
+        if let Some(status) = report("-e", expr, id, run(expr, id, &env, true)) {
+            process::exit(status);
+        }
+    }
+    if let Some(script) = options.script.first() {
+        if let Some(status) = run_file(script, &env) {
+            process::exit(status);
         }
+    } else if options.eval.is_empty() {
+        process::exit(repl(&env));
     }
 }
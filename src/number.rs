@@ -1,11 +1,38 @@
+use std::cmp::Ordering;
 use std::fmt;
-use std::ops::{Add, Mul, Sub, Div, Neg};
+use std::ops::{Add, Mul, Sub, Neg};
+use std::rc::Rc;
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+use crate::bigint::BigInt;
+use crate::error::ErrorKind;
+use crate::rational::Rational;
+
+/// The size in bits above which `expt` refuses to compute an exact result and
+/// number literals are rejected. Multiplying, reading and printing big
+/// integers takes quadratic time, so this keeps each well under a second.
+const MAX_EXACT_BITS: u64 = 1 << 17;
+
+/// A Scheme number. Integers that do not fit in an `i64` are promoted to
+/// `Big`, and results that fit again are demoted back to `Int`, so `Big`
+/// never holds a value in the `i64` range. Likewise, `Rational` never holds
+/// an integer, and `Complex` never has an exact zero imaginary part. All
+/// variants but `Float` are exact, while complex numbers are exact if both
+/// their parts are.
+#[derive(Debug, Clone)]
 pub enum Number {
     Int(i64),
+    Big(Rc<BigInt>),
+    Rational(Rc<Rational>),
     Float(f64),
+    Complex(Rc<Complex>),
+}
+
+/// A complex number in rectangular form. Both parts are real numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Complex {
+    pub real: Number,
+    pub imag: Number,
 }
 
 impl From<i64> for Number {
@@ -20,19 +47,218 @@ impl From<f64> for Number {
     }
 }
 
+impl From<BigInt> for Number {
+    fn from(v: BigInt) -> Number {
+        match v.to_i64() {
+            Some(v) => Self::Int(v),
+            None => Self::Big(Rc::new(v)),
+        }
+    }
+}
+
+impl From<Rational> for Number {
+    fn from(v: Rational) -> Number {
+        if v.is_integer() {
+            Self::from(v.numerator().clone())
+        } else {
+            Self::Rational(Rc::new(v))
+        }
+    }
+}
+
 pub struct ParseNumberError {}
 
 impl FromStr for Number {
     type Err = ParseNumberError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Ok(v) = s.parse::<i64>() {
-            Ok(Number::Int(v))
-        } else if let Ok(v) = s.parse::<f64>() {
-            Ok(Number::Float(v))
-        } else {
-            Err(Self::Err {})
+        Number::from_str_radix(s, 10)
+    }
+}
+
+/// Parses a real number or a complex number written in rectangular form like
+/// `3+4i` or `-i`, or in polar form like `1@0.5`. Decimals are read exactly
+/// if `exact` is set.
+fn parse_complex(s: &str, radix: u32, exact: bool) -> Result<Number, ParseNumberError> {
+    if let Ok(v) = parse_real(s, radix, exact) {
+        return Ok(v);
+    }
+    if let Some((magnitude, angle)) = s.split_once('@') {
+        return Ok(Number::polar(
+            parse_real(magnitude, radix, exact)?,
+            parse_real(angle, radix, exact)?,
+        ));
+    }
+    let body = s.strip_suffix(['i', 'I']).ok_or(ParseNumberError {})?;
+    // The imaginary part starts at the last sign that is not part of a
+    // decimal exponent.
+    let split = body
+        .char_indices()
+        .rev()
+        .find(|&(i, c)| {
+            (c == '+' || c == '-') && !(radix == 10 && body[..i].ends_with(['e', 'E']))
+        })
+        .map(|(i, _)| i)
+        .ok_or(ParseNumberError {})?;
+    let real = match &body[..split] {
+        "" => Number::Int(0),
+        real => parse_real(real, radix, exact)?,
+    };
+    let imag = match &body[split..] {
+        "+" => Number::Int(1),
+        "-" => Number::Int(-1),
+        imag => parse_real(imag, radix, exact)?,
+    };
+    Ok(Number::rectangular(real, imag))
+}
+
+/// Parses an integer, a fraction, or in radix 10 a decimal or one of
+/// `+inf.0`, `-inf.0`, `+nan.0` and `-nan.0`.
+fn parse_real(s: &str, radix: u32, exact: bool) -> Result<Number, ParseNumberError> {
+    // Reading digits into a big integer takes quadratic time.
+    if s.len() as f64 * f64::from(radix).log2() > MAX_EXACT_BITS as f64 {
+        return Err(ParseNumberError {});
+    }
+    if let Ok(v) = i64::from_str_radix(s, radix) {
+        return Ok(Number::Int(v));
+    }
+    if let Ok(v) = BigInt::from_str_radix(s, radix) {
+        return Ok(Number::from(v));
+    }
+    if let Some((numerator, denominator)) = s.split_once('/') {
+        let numerator = BigInt::from_str_radix(numerator, radix).map_err(|_| ParseNumberError {})?;
+        let denominator = match denominator.as_bytes().first() {
+            Some(b'+') | Some(b'-') => return Err(ParseNumberError {}),
+            _ => BigInt::from_str_radix(denominator, radix).map_err(|_| ParseNumberError {})?,
+        };
+        if denominator.is_zero() {
+            return Err(ParseNumberError {});
         }
+        return Ok(Number::from(Rational::new(numerator, denominator)));
+    }
+    if radix != 10 {
+        return Err(ParseNumberError {});
+    }
+    match s.to_ascii_lowercase().as_str() {
+        "+inf.0" => return Ok(Number::Float(f64::INFINITY)),
+        "-inf.0" => return Ok(Number::Float(f64::NEG_INFINITY)),
+        "+nan.0" => return Ok(Number::Float(f64::NAN)),
+        "-nan.0" => return Ok(Number::Float(-f64::NAN)),
+        _ => {}
+    }
+    // Rust also accepts spellings like `inf` and `NaN`, which are identifiers
+    // in Scheme.
+    if !s.bytes().all(|b| b.is_ascii_digit() || matches!(b, b'+' | b'-' | b'.' | b'e' | b'E')) {
+        return Err(ParseNumberError {});
+    }
+    let v = s.parse::<f64>().map_err(|_| ParseNumberError {})?;
+    if exact {
+        return parse_exact_decimal(s).ok_or(ParseNumberError {});
+    }
+    Ok(Number::Float(v))
+}
+
+/// Reads a decimal that `f64` accepts as the exact fraction it denotes.
+fn parse_exact_decimal(s: &str) -> Option<Number> {
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], s[i + 1..].parse::<i64>().ok()?),
+        None => (s, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let numerator = Number::from(format!("{}{}", integer, fraction).parse::<BigInt>().ok()?);
+    let exponent = exponent.checked_sub(fraction.len() as i64)?;
+    if exponent.unsigned_abs() > MAX_EXACT_BITS / 4 {
+        return None;
+    }
+    let scale = Number::from(BigInt::from(10).pow(exponent.unsigned_abs()));
+    if exponent < 0 {
+        numerator.checked_div(&scale)
+    } else {
+        Some(numerator * scale)
+    }
+}
+
+impl Number {
+    /// Parses a number in the given radix, which must be 2, 8, 10 or 16.
+    /// The number may start with a radix prefix `#b`, `#o`, `#d` or `#x`
+    /// overriding `radix`, and an exactness prefix `#e` or `#i`, in either
+    /// order.
+    pub fn from_str_radix(s: &str, radix: u32) -> Result<Number, ParseNumberError> {
+        let (mut s, mut radix, mut radix_prefix, mut exactness) = (s, radix, false, None);
+        while let Some(prefix) = s.get(..2).filter(|prefix| prefix.starts_with('#')) {
+            match prefix.to_ascii_lowercase().as_str() {
+                "#b" | "#o" | "#d" | "#x" if !radix_prefix => {
+                    radix = match &prefix[1..] {
+                        "b" | "B" => 2,
+                        "o" | "O" => 8,
+                        "d" | "D" => 10,
+                        _ => 16,
+                    };
+                    radix_prefix = true;
+                }
+                "#e" if exactness.is_none() => exactness = Some(true),
+                "#i" if exactness.is_none() => exactness = Some(false),
+                _ => return Err(ParseNumberError {}),
+            }
+            s = &s[2..];
+        }
+        let n = parse_complex(s, radix, exactness == Some(true))?;
+        match exactness {
+            Some(true) => n.to_exact().ok_or(ParseNumberError {}),
+            Some(false) => Ok(n.to_inexact()),
+            None => Ok(n),
+        }
+    }
+
+    /// Formats the number in the given radix, which must be 2, 8, 10 or 16.
+    /// Returns `None` for inexact numbers in radices other than 10.
+    pub fn to_string_radix(&self, radix: u32) -> Option<String> {
+        match self {
+            _ if radix == 10 => Some(self.to_string()),
+            Number::Int(v) => Some(BigInt::from(*v).to_str_radix(radix)),
+            Number::Big(v) => Some(v.to_str_radix(radix)),
+            Number::Rational(v) => Some(format!(
+                "{}/{}",
+                v.numerator().to_str_radix(radix),
+                v.denominator().to_str_radix(radix)
+            )),
+            Number::Float(_) => None,
+            Number::Complex(z) => Some(format_rectangular(
+                z.real.to_string_radix(radix)?,
+                z.imag.to_string_radix(radix)?,
+            )),
+        }
+    }
+}
+
+/// Joins the formatted parts of a complex number, leaving out an exact zero
+/// real part and the digit of an exact imaginary part of one.
+fn format_rectangular(real: String, imag: String) -> String {
+    let real = if real == "0" { String::new() } else { real };
+    match imag.as_str() {
+        "1" => format!("{}+i", real),
+        "-1" => format!("{}-i", real),
+        _ if imag.starts_with(['+', '-']) => format!("{}{}i", real, imag),
+        _ => format!("{}+{}i", real, imag),
+    }
+}
+
+/// Formats a float in the shortest form that reads back as the same value.
+fn format_float(v: f64) -> String {
+    if v.is_nan() {
+        return if v.is_sign_negative() { "-nan.0" } else { "+nan.0" }.to_string();
+    }
+    if v.is_infinite() {
+        return if v < 0.0 { "-inf.0" } else { "+inf.0" }.to_string();
+    }
+    if v != 0.0 && (v.abs() >= 1e21 || v.abs() < 1e-7) {
+        return format!("{:e}", v);
+    }
+    let s = v.to_string();
+    if s.contains('.') {
+        s
+    } else {
+        s + ".0"
     }
 }
 
@@ -40,27 +266,586 @@ impl fmt::Display for Number {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Number::Int(v) => write!(f, "{}", v),
-            Number::Float(v) => write!(f, "{:+.4e}", v),
+            Number::Big(v) => write!(f, "{}", v),
+            Number::Rational(v) => write!(f, "{}", v),
+            Number::Float(v) => write!(f, "{}", format_float(*v)),
+            Number::Complex(z) => write!(
+                f,
+                "{}",
+                format_rectangular(z.real.to_string(), z.imag.to_string())
+            ),
         }
     }
 }
 
 impl Number {
-    fn apply_binary_op<OpInt, OpFloat, R>(
-        self,
-        other: Self,
+    /// Returns the complex number `real + imag i`, or just `real` if `imag`
+    /// is an exact zero. Both parts must be real.
+    pub fn rectangular(real: Number, imag: Number) -> Number {
+        if imag.is_exact_zero() {
+            real
+        } else {
+            Number::Complex(Rc::new(Complex { real, imag }))
+        }
+    }
+
+    /// Returns the complex number with the given magnitude and angle, both
+    /// of which must be real.
+    pub fn polar(magnitude: Number, angle: Number) -> Number {
+        if angle.is_exact_zero() {
+            return magnitude;
+        }
+        let (magnitude, angle) = (magnitude.to_f64(), angle.to_f64());
+        Number::rectangular(
+            Number::Float(magnitude * angle.cos()),
+            Number::Float(magnitude * angle.sin()),
+        )
+    }
+
+    pub fn is_exact(&self) -> bool {
+        match self {
+            Number::Float(_) => false,
+            Number::Complex(z) => z.real.is_exact() && z.imag.is_exact(),
+            _ => true,
+        }
+    }
+
+    fn is_exact_zero(&self) -> bool {
+        matches!(self, Number::Int(0))
+    }
+
+    pub fn is_real(&self) -> bool {
+        !matches!(self, Number::Complex(_))
+    }
+
+    /// Whether two numbers are the same for `eqv?`, which requires them to
+    /// be equal and of the same exactness. Inexact numbers are compared bit
+    /// for bit, so `0.0` and `-0.0` differ while a NaN is the same as itself.
+    pub fn is_eqv(&self, other: &Number) -> bool {
+        match (self, other) {
+            (Number::Float(a), Number::Float(b)) => a.to_bits() == b.to_bits(),
+            (Number::Complex(a), Number::Complex(b)) => {
+                a.real.is_eqv(&b.real) && a.imag.is_eqv(&b.imag)
+            }
+            (Number::Float(_), _) | (_, Number::Float(_)) => false,
+            (Number::Complex(_), _) | (_, Number::Complex(_)) => false,
+            _ => self == other,
+        }
+    }
+
+    /// Returns the exact number equal to this one, or `None` if it has a
+    /// part that is infinite or NaN.
+    pub fn to_exact(&self) -> Option<Number> {
+        match self {
+            Number::Float(v) => Rational::from_f64(*v).map(Number::from),
+            Number::Complex(z) => Some(Number::rectangular(
+                z.real.to_exact()?,
+                z.imag.to_exact()?,
+            )),
+            exact => Some(exact.clone()),
+        }
+    }
+
+    /// Returns the nearest inexact number.
+    pub fn to_inexact(&self) -> Number {
+        match self {
+            Number::Complex(z) => Number::rectangular(z.real.to_inexact(), z.imag.to_inexact()),
+            real => Number::Float(real.to_f64()),
+        }
+    }
+
+    pub fn real_part(&self) -> Number {
+        match self {
+            Number::Complex(z) => z.real.clone(),
+            real => real.clone(),
+        }
+    }
+
+    pub fn imag_part(&self) -> Number {
+        match self {
+            Number::Complex(z) => z.imag.clone(),
+            real => real.with_exactness(Number::Int(0)),
+        }
+    }
+
+    pub fn magnitude(&self) -> Number {
+        match self {
+            Number::Complex(z) => {
+                let (real, imag) = (&z.real, &z.imag);
+                (real.clone() * real.clone() + imag.clone() * imag.clone()).sqrt()
+            }
+            real if real.is_negative() => -real.clone(),
+            real => real.clone(),
+        }
+    }
+
+    pub fn angle(&self) -> Number {
+        match self {
+            Number::Complex(z) => Number::Float(z.imag.to_f64().atan2(z.real.to_f64())),
+            Number::Float(v) => Number::Float(0f64.atan2(*v)),
+            real if real.is_negative() => Number::Float(std::f64::consts::PI),
+            _ => Number::Int(0),
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        match self {
+            Number::Int(_) | Number::Big(_) => true,
+            Number::Float(v) => v.is_finite() && v.fract() == 0.0,
+            Number::Rational(_) | Number::Complex(_) => false,
+        }
+    }
+
+    /// Whether this is a real number that can be written as a fraction,
+    /// which excludes infinities and NaNs.
+    pub fn is_rational(&self) -> bool {
+        match self {
+            Number::Float(v) => v.is_finite(),
+            Number::Complex(_) => false,
+            _ => true,
+        }
+    }
+
+    pub fn is_nan(&self) -> bool {
+        match self {
+            Number::Float(v) => v.is_nan(),
+            Number::Complex(z) => z.real.is_nan() || z.imag.is_nan(),
+            _ => false,
+        }
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        match self {
+            Number::Float(v) => v.is_infinite(),
+            Number::Complex(z) => z.real.is_infinite() || z.imag.is_infinite(),
+            _ => false,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Int(v) => *v == 0,
+            Number::Float(v) => *v == 0.0,
+            Number::Complex(z) => z.real.is_zero() && z.imag.is_zero(),
+            _ => false,
+        }
+    }
+
+    /// Whether a real number is less than zero.
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Number::Int(v) => *v < 0,
+            Number::Big(v) => v.is_negative(),
+            Number::Rational(v) => v.numerator().is_negative(),
+            Number::Float(v) => *v < 0.0,
+            Number::Complex(_) => false,
+        }
+    }
+
+    /// Whether a real number is greater than zero.
+    pub fn is_positive(&self) -> bool {
+        match self {
+            Number::Complex(_) => false,
+            real => !real.is_negative() && !real.is_zero() && !real.is_nan(),
+        }
+    }
+
+    /// The largest integer not greater than a real number.
+    pub fn floor(&self) -> Number {
+        match self {
+            Number::Rational(v) => Number::from(v.floor()),
+            Number::Float(v) => Number::Float(v.floor()),
+            integer => integer.clone(),
+        }
+    }
+
+    /// The smallest integer not less than a real number.
+    pub fn ceiling(&self) -> Number {
+        -(-self.clone()).floor()
+    }
+
+    /// The integer closest to a real number whose absolute value is not
+    /// larger.
+    pub fn truncate(&self) -> Number {
+        if self.is_negative() {
+            self.ceiling()
+        } else {
+            self.floor()
+        }
+    }
+
+    /// The integer closest to a real number, rounding to even when halfway
+    /// between two integers.
+    pub fn round(&self) -> Number {
+        match self {
+            Number::Float(v) => Number::Float(v.round_ties_even()),
+            Number::Rational(_) => {
+                let floor = self.floor();
+                let half = Number::from(Rational::new(BigInt::from(1), BigInt::from(2)));
+                match (self.clone() - floor.clone()).partial_cmp(&half) {
+                    Some(Ordering::Less) => floor,
+                    Some(Ordering::Equal) if floor.is_even() => floor,
+                    _ => floor + Number::Int(1),
+                }
+            }
+            integer => integer.clone(),
+        }
+    }
+
+    /// Divides by `other`. Returns `None` if both are exact and `other` is
+    /// zero, while inexact division by zero gives an infinity or a NaN.
+    pub fn checked_div(&self, other: &Number) -> Option<Number> {
+        self.apply_binary_op(
+            other,
+            |a, b| match a.checked_rem(b) {
+                Some(0) => a.checked_div(b).map(|v| Some(Number::Int(v))),
+                _ => None,
+            },
+            |a, b| {
+                if b.is_zero() {
+                    None
+                } else {
+                    Some(Number::from(Rational::new(a.clone(), b.clone())))
+                }
+            },
+            |a, b| a.checked_div(b).map(Number::from),
+            |a, b| Some(Number::Float(a / b)),
+            |a, b| {
+                let scale = b.real.clone() * b.real.clone() + b.imag.clone() * b.imag.clone();
+                let real = a.real.clone() * b.real.clone() + a.imag.clone() * b.imag.clone();
+                let imag = a.imag * b.real - a.real * b.imag;
+                Some(Number::rectangular(real.checked_div(&scale)?, imag.checked_div(&scale)?))
+            },
+        )
+    }
+
+    /// Divides integers, rounding the quotient towards zero, and returns the
+    /// quotient and the remainder, which has the sign of the dividend. The
+    /// result is inexact if either operand is. Returns `None` if `other` is
+    /// zero.
+    ///
+    /// Both operands must be integers.
+    pub fn truncate_div(&self, other: &Number) -> Option<(Number, Number)> {
+        if other.is_zero() {
+            return None;
+        }
+        Some(self.apply_binary_op(
+            other,
+            |a, b| Some((Number::Int(a.checked_div(b)?), Number::Int(a % b))),
+            |a, b| {
+                let (quotient, remainder) = a.div_rem(b);
+                (Number::from(quotient), Number::from(remainder))
+            },
+            |_, _| unreachable!("integer division of rationals"),
+            |a, b| (Number::Float((a / b).trunc()), Number::Float(a % b)),
+            |_, _| unreachable!("integer division of complex numbers"),
+        ))
+    }
+
+    /// Divides integers like `truncate_div`, but rounds the quotient down, so
+    /// that the remainder has the sign of the divisor.
+    pub fn floor_div(&self, other: &Number) -> Option<(Number, Number)> {
+        let (quotient, remainder) = self.truncate_div(other)?;
+        if !remainder.is_zero() && remainder.is_negative() != other.is_negative() {
+            Some((quotient - Number::Int(1), remainder + other.clone()))
+        } else {
+            Some((quotient, remainder))
+        }
+    }
+
+    /// Whether an integer is divisible by two.
+    pub fn is_even(&self) -> bool {
+        match self.truncate_div(&Number::Int(2)) {
+            Some((_, remainder)) => remainder.is_zero(),
+            None => unreachable!("division by two"),
+        }
+    }
+
+    /// The greatest common divisor of two integers, which is never negative.
+    pub fn gcd(&self, other: &Number) -> Number {
+        let gcd = match (self.to_exact(), other.to_exact()) {
+            (Some(a), Some(b)) => Number::from(a.to_big().gcd(&b.to_big())),
+            _ => unreachable!("gcd of non-integers"),
+        };
+        if other.is_exact() {
+            self.with_exactness(gcd)
+        } else {
+            gcd.to_inexact()
+        }
+    }
+
+    /// The least common multiple of two integers, which is never negative.
+    pub fn lcm(&self, other: &Number) -> Number {
+        if self.is_zero() || other.is_zero() {
+            return self.clone() * other.clone();
+        }
+        let gcd = self.gcd(other);
+        match (self.clone() * other.clone()).magnitude().checked_div(&gcd) {
+            Some(lcm) => lcm,
+            None => unreachable!("the gcd of non-zero integers is non-zero"),
+        }
+    }
+
+    /// Raises this number to the power `exponent`. The result is exact if
+    /// both are exact and `exponent` is an integer. Fails if this is an exact
+    /// zero and `exponent` is negative, or if an exact result would be too
+    /// large.
+    pub fn expt(&self, exponent: &Number) -> Result<Number, ErrorKind> {
+        if self.is_exact() && exponent.is_exact() && exponent.is_integer() {
+            return self.exact_expt(exponent);
+        }
+        if exponent.is_zero() {
+            return Ok(Number::Float(1.0));
+        }
+        // Zero to a real power is left to powf, which gives +inf.0 for
+        // negative powers. To a non-real power, it is only defined when the
+        // real part is positive.
+        if self.is_zero() && !exponent.is_real() {
+            return if exponent.real_part().is_positive() {
+                Ok(Number::Float(0.0))
+            } else {
+                Err(ErrorKind::DivisionByZero)
+            };
+        }
+        if let (true, true) = (self.is_real(), exponent.is_real()) {
+            let result = self.to_f64().powf(exponent.to_f64());
+            if !result.is_nan() {
+                return Ok(Number::Float(result));
+            }
+        }
+        Ok((exponent.clone() * self.log()).exp())
+    }
+
+    /// Raises this exact number to an exact integer power by repeated squaring.
+    fn exact_expt(&self, exponent: &Number) -> Result<Number, ErrorKind> {
+        if self.is_zero() {
+            return match exponent {
+                e if e.is_negative() => Err(ErrorKind::DivisionByZero),
+                e if e.is_zero() => Ok(Number::Int(1)),
+                _ => Ok(Number::Int(0)),
+            };
+        }
+        if exponent.to_f64().abs() * self.exact_log2() > MAX_EXACT_BITS as f64 {
+            let message = format!("exact result of (expt {} {}) is too large", self, exponent);
+            return Err(ErrorKind::ImplementationRestriction(message));
+        }
+        let power = match exponent {
+            Number::Int(power) => *power,
+            // Only 1 and -1 have a small enough power for such exponents.
+            big => if big.is_even() { 2 } else { 1 },
+        };
+        let mut result = Number::Int(1);
+        let mut base = self.clone();
+        let mut remaining = power.unsigned_abs();
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                result = result * base.clone();
+            }
+            remaining >>= 1;
+            if remaining > 0 {
+                base = base.clone() * base;
+            }
+        }
+        if power < 0 {
+            Number::Int(1).checked_div(&result).ok_or(ErrorKind::DivisionByZero)
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// About the number of bits by which an exact power of this number grows
+    /// each time the exponent increases by one.
+    fn exact_log2(&self) -> f64 {
+        match self {
+            Number::Int(v) => (v.unsigned_abs() as f64).log2(),
+            Number::Big(v) => v.log2(),
+            Number::Rational(v) => v.numerator().log2() + v.denominator().log2(),
+            Number::Float(v) => v.abs().log2(),
+            // The magnitude is at most the larger part times the square root
+            // of two.
+            Number::Complex(z) => z.real.exact_log2().max(z.imag.exact_log2()) + 0.5,
+        }
+    }
+
+    /// The exponential function.
+    pub fn exp(&self) -> Number {
+        match self {
+            Number::Complex(z) => Number::polar(
+                Number::Float(z.real.to_f64().exp()),
+                z.imag.clone(),
+            ),
+            real => Number::Float(real.to_f64().exp()),
+        }
+    }
+
+    /// The natural logarithm, whose imaginary part is the angle of this
+    /// number.
+    pub fn log(&self) -> Number {
+        let magnitude = Number::Float(self.magnitude().to_f64().ln());
+        if self.is_real() && !self.is_negative() {
+            magnitude
+        } else {
+            Number::rectangular(magnitude, self.angle())
+        }
+    }
+
+    /// The principal square root, which is exact if this number is exact and
+    /// its square root is a rational number.
+    pub fn sqrt(&self) -> Number {
+        match self {
+            Number::Complex(_) => {
+                let (magnitude, angle) = (self.magnitude().to_f64(), self.angle().to_f64());
+                Number::polar(Number::Float(magnitude.sqrt()), Number::Float(angle / 2.0))
+            }
+            real if real.is_negative() => {
+                Number::rectangular(Number::Int(0), (-real.clone()).sqrt())
+            }
+            Number::Float(v) => Number::Float(v.sqrt()),
+            exact => {
+                let rational = exact.to_rational();
+                let numerator = rational.numerator().sqrt();
+                let denominator = rational.denominator().sqrt();
+                if &(&numerator * &numerator) == rational.numerator()
+                    && &(&denominator * &denominator) == rational.denominator()
+                {
+                    Number::from(Rational::new(numerator, denominator))
+                } else {
+                    let v = exact.to_f64();
+                    if v.is_normal() {
+                        return Number::Float(v.sqrt());
+                    }
+                    // Out of range as a float, so take the root of n/d as
+                    // isqrt(n*d*4^64) / (d*2^64), which converts without
+                    // overflowing or losing precision.
+                    let scaled = (rational.numerator() * rational.denominator()).shl(128);
+                    let root = Rational::new(scaled.sqrt(), rational.denominator().shl(64));
+                    Number::Float(root.to_f64())
+                }
+            }
+        }
+    }
+
+    /// The largest integer whose square is not greater than this number,
+    /// and the difference between the two. Returns `None` unless this is a
+    /// non-negative exact integer.
+    pub fn exact_integer_sqrt(&self) -> Option<(Number, Number)> {
+        if !self.is_exact() || !self.is_integer() || self.is_negative() {
+            return None;
+        }
+        let root = Number::from(self.to_big().sqrt());
+        let remainder = self.clone() - root.clone() * root.clone();
+        Some((root, remainder))
+    }
+
+    /// The numerator of a real number in lowest terms, or `None` for
+    /// infinities, NaNs and non-real numbers.
+    pub fn numerator(&self) -> Option<Number> {
+        if !self.is_real() {
+            return None;
+        }
+        let numerator = match self.to_exact()? {
+            Number::Rational(v) => Number::from(v.numerator().clone()),
+            integer => integer,
+        };
+        Some(self.with_exactness(numerator))
+    }
+
+    /// The positive denominator of a real number in lowest terms, or `None`
+    /// for infinities, NaNs and non-real numbers.
+    pub fn denominator(&self) -> Option<Number> {
+        if !self.is_real() {
+            return None;
+        }
+        let denominator = match self.to_exact()? {
+            Number::Rational(v) => Number::from(v.denominator().clone()),
+            _ => Number::Int(1),
+        };
+        Some(self.with_exactness(denominator))
+    }
+
+    /// Converts the exact number `v` to be inexact if this number is.
+    fn with_exactness(&self, v: Number) -> Number {
+        if self.is_exact() {
+            v
+        } else {
+            v.to_inexact()
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Number::Int(v) => *v as f64,
+            Number::Big(v) => v.to_f64(),
+            Number::Rational(v) => v.to_f64(),
+            Number::Float(v) => *v,
+            Number::Complex(_) => unreachable!("complex numbers are never converted to floats"),
+        }
+    }
+
+    fn to_complex(&self) -> Complex {
+        match self {
+            Number::Complex(z) => (**z).clone(),
+            real => Complex {
+                real: real.clone(),
+                imag: Number::Int(0),
+            },
+        }
+    }
+
+    fn to_big(&self) -> BigInt {
+        match self {
+            Number::Int(v) => BigInt::from(*v),
+            Number::Big(v) => (**v).clone(),
+            _ => unreachable!("only integers are converted to bignums"),
+        }
+    }
+
+    fn to_rational(&self) -> Rational {
+        match self {
+            Number::Rational(v) => (**v).clone(),
+            Number::Float(_) | Number::Complex(_) => {
+                unreachable!("only exact reals are converted to rationals")
+            }
+            integer => Rational::from(integer.to_big()),
+        }
+    }
+
+    /// Applies the operation for the representation both operands can be
+    /// converted to: fixnums stay fixnums, integers become bignums if either
+    /// is one, exact numbers become rationals if either is one, reals combined
+    /// with a float become floats, and anything combined with a complex number
+    /// becomes complex. A fixnum operation that overflows or has no fixnum
+    /// result returns `None` and is retried on bignums.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_binary_op<OpInt, OpBig, OpRational, OpFloat, OpComplex, R>(
+        &self,
+        other: &Self,
         op_int: OpInt,
+        op_big: OpBig,
+        op_rational: OpRational,
         op_float: OpFloat,
+        op_complex: OpComplex,
     ) -> R
     where
-        OpInt: Fn(i64, i64) -> R,
+        OpInt: Fn(i64, i64) -> Option<R>,
+        OpBig: Fn(&BigInt, &BigInt) -> R,
+        OpRational: Fn(&Rational, &Rational) -> R,
         OpFloat: Fn(f64, f64) -> R,
+        OpComplex: Fn(Complex, Complex) -> R,
     {
         match (self, other) {
-            (Number::Int(a), Number::Int(b)) => op_int(a, b),
-            (Number::Float(a), Number::Float(b)) => op_float(a, b),
-            (Number::Int(a), Number::Float(b)) => op_float(a as f64, b),
-            (Number::Float(a), Number::Int(b)) => op_float(a, b as f64),
+            (Number::Complex(_), _) | (_, Number::Complex(_)) => {
+                op_complex(self.to_complex(), other.to_complex())
+            }
+            (Number::Int(a), Number::Int(b)) => op_int(*a, *b)
+                .unwrap_or_else(|| op_big(&BigInt::from(*a), &BigInt::from(*b))),
+            (Number::Float(_), _) | (_, Number::Float(_)) => {
+                op_float(self.to_f64(), other.to_f64())
+            }
+            (Number::Rational(_), _) | (_, Number::Rational(_)) => {
+                op_rational(&self.to_rational(), &other.to_rational())
+            }
+            _ => op_big(&self.to_big(), &other.to_big()),
         }
     }
 }
@@ -69,8 +854,13 @@ impl Neg for Number {
     type Output = Self;
     fn neg(self) -> Self::Output {
         match self {
-            Number::Int(v) => Number::Int(-v),
-            Number::Float(v) => Number::Float(-v)
+            Number::Int(v) => v
+                .checked_neg()
+                .map_or_else(|| Number::from(-&BigInt::from(v)), Number::Int),
+            Number::Big(v) => Number::from(-&*v),
+            Number::Rational(v) => Number::from(-&*v),
+            Number::Float(v) => Number::Float(-v),
+            Number::Complex(z) => Number::rectangular(-z.real.clone(), -z.imag.clone()),
         }
     }
 }
@@ -78,45 +868,71 @@ impl Neg for Number {
 impl Add for Number {
     type Output = Self;
     fn add(self, other: Self) -> Self::Output {
-        self.apply_binary_op(other, |a, b| Number::Int(a + b), |a, b| Number::Float(a + b))
+        self.apply_binary_op(
+            &other,
+            |a, b| a.checked_add(b).map(Number::Int),
+            |a, b| Number::from(a + b),
+            |a, b| Number::from(a + b),
+            |a, b| Number::Float(a + b),
+            |a, b| Number::rectangular(a.real + b.real, a.imag + b.imag),
+        )
     }
 }
 
 impl Sub for Number {
     type Output = Self;
     fn sub(self, other: Self) -> Self::Output {
-        self.apply_binary_op(other, |a, b| Number::Int(a - b), |a, b| Number::Float(a - b))
+        self.apply_binary_op(
+            &other,
+            |a, b| a.checked_sub(b).map(Number::Int),
+            |a, b| Number::from(a - b),
+            |a, b| Number::from(a - b),
+            |a, b| Number::Float(a - b),
+            |a, b| Number::rectangular(a.real - b.real, a.imag - b.imag),
+        )
     }
 }
 
 impl Mul for Number {
     type Output = Self;
     fn mul(self, other: Self) -> Self::Output {
-        self.apply_binary_op(other, |a, b| Number::Int(a * b), |a, b| Number::Float(a * b))
-    }
-}
-
-impl Div for Number {
-    type Output = Self;
-    fn div(self, other: Self) -> Self::Output {
-        self.apply_binary_op(other, |a, b| {
-            if a % b == 0 {
-                Self::Int(a / b)
-            } else {
-                Self::Float(a as f64 / b as f64)
-            }
-        }, |a, b| Number::Float(a / b))
+        self.apply_binary_op(
+            &other,
+            |a, b| a.checked_mul(b).map(Number::Int),
+            |a, b| Number::from(a * b),
+            |a, b| Number::from(a * b),
+            |a, b| Number::Float(a * b),
+            |a, b| {
+                let real = a.real.clone() * b.real.clone() - a.imag.clone() * b.imag.clone();
+                let imag = a.real * b.imag + a.imag * b.real;
+                Number::rectangular(real, imag)
+            },
+        )
     }
 }
 
 impl PartialEq for Number {
     fn eq(&self, other: &Self) -> bool {
-        self.apply_binary_op(*other, |a, b| a == b, |a, b| a == b)
+        self.apply_binary_op(
+            other,
+            |a, b| Some(a == b),
+            |a, b| a == b,
+            |a, b| a == b,
+            |a, b| a == b,
+            |a, b| a == b,
+        )
     }
 }
 
 impl PartialOrd for Number {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.apply_binary_op(*other, |a, b| a.partial_cmp(&b), |a, b| a.partial_cmp(&b))
+        self.apply_binary_op(
+            other,
+            |a, b| Some(a.partial_cmp(&b)),
+            |a, b| a.partial_cmp(b),
+            |a, b| a.partial_cmp(b),
+            |a, b| a.partial_cmp(&b),
+            |_, _| None,
+        )
     }
 }
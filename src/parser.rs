@@ -1,53 +1,266 @@
+use std::cell::RefCell;
+use std::convert::TryFrom;
 use std::iter::Peekable;
+use std::rc::Rc;
 
+use crate::error::SchemeError;
 use crate::expression::Expression;
-use crate::tokenizer::Token;
+use crate::number::Number;
+use crate::span::{SourceId, Span};
+use crate::symbol::Symbol;
+use crate::tokenizer::{tokenize, Token};
 
-pub struct Parser<I: Iterator<Item = Token>> {
+/// How deeply data may nest before the parser gives up, to keep deeply
+/// nested input from overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
+/// Reads data from a token stream. After an error the parser skips ahead to
+/// the next top-level form, so it can report several errors in one pass.
+pub struct Parser<I: Iterator<Item = Result<(Token, Span), SchemeError>>> {
     iter: Peekable<I>,
+    /// The number of nested `single` calls in progress.
+    depth: usize,
+    /// The number of lists, vectors and bytevectors that have been opened but
+    /// not closed.
+    open: usize,
 }
 
-impl<I: Iterator<Item = Token>> Parser<I> {
+impl<I: Iterator<Item = Result<(Token, Span), SchemeError>>> Parser<I> {
     pub fn new(iter: I) -> Self {
         Parser {
             iter: iter.peekable(),
+            depth: 0,
+            open: 0,
         }
     }
 
-    fn single(&mut self) -> Result<Option<Expression>, String> {
-        match self.iter.next() {
-            Some(token) => match token {
-                Token::LParen => {
-                    let mut elements: Vec<Expression> = Vec::new();
-                    loop {
-                        match self.iter.peek() {
-                            Some(Token::RParen) => {
-                                self.iter.next();
-                                break;
-                            }
-                            None => return Err("Unexpected EOF".to_string()),
-                            _ => match self.single()? {
-                                Some(expr) => elements.push(expr),
-                                None => panic!("w00t"),
-                            },
-                        }
-                    }
-                    Ok(Some(Expression::Combination(elements)))
+    /// Skips the rest of a form in which an error occurred, up to the next `(`
+    /// at the start of a line, which is taken to start the next top-level form.
+    fn resynchronize(&mut self) {
+        while let Some(token) = self.iter.peek() {
+            if let Ok((Token::LParen, span)) = token {
+                if span.column == 1 {
+                    break;
                 }
-                Token::RParen => Err("Unexpected ')'".to_string()),
-                Token::Identifier(id) => Ok(Some(Expression::Identifier(id))),
-                Token::StringLiteral(st) => Ok(Some(Expression::StringLiteral(st))),
-                Token::NumberLiteral(v) => Ok(Some(Expression::NumberLiteral(v))),
-            },
-            None => Ok(None),
+            }
+            self.iter.next();
+        }
+        self.open = 0;
+    }
+
+    /// Expands a reader abbreviation such as `'x` into `(quote x)`.
+    fn abbreviation(
+        &mut self,
+        name: &str,
+        span: Span,
+    ) -> Result<Option<(Expression, Span)>, SchemeError> {
+        match self.single()? {
+            Some((expr, expr_span)) => {
+                let elements = vec![Expression::Identifier(Symbol::new(name), span), expr];
+                let span = span.to(expr_span);
+                Ok(Some((Expression::Combination(elements.into(), span), span)))
+            }
+            None => Err(SchemeError::incomplete("expecting a datum").at(span)),
         }
     }
+
+    /// Skips the datum following a `#;` datum comment.
+    fn skip_datum(&mut self, span: Span) -> Result<(), SchemeError> {
+        match self.single()? {
+            Some(_) => Ok(()),
+            None => Err(SchemeError::incomplete("expecting a datum after '#;'").at(span)),
+        }
+    }
+
+    /// Reads the elements following an opening `(`, `#(` or `#u8(` up to the
+    /// matching `)`, returning them with the span of the whole sequence.
+    fn elements(
+        &mut self,
+        opener: &Token,
+        span: Span,
+    ) -> Result<(Vec<Expression>, Span), SchemeError> {
+        let unclosed = || {
+            let message = format!("unclosed '{}'", opener);
+            SchemeError::incomplete(&message).at(span)
+        };
+        let mut elements: Vec<Expression> = Vec::new();
+        self.open += 1;
+        loop {
+            match self.iter.peek() {
+                Some(Ok((Token::RParen, end_span))) => {
+                    let span = span.to(*end_span);
+                    self.iter.next();
+                    self.open -= 1;
+                    return Ok((elements, span));
+                }
+                Some(Ok((Token::DatumComment, comment_span))) => {
+                    let comment_span = *comment_span;
+                    self.iter.next();
+                    self.skip_datum(comment_span)?;
+                }
+                None => return Err(unclosed()),
+                _ => match self.single()? {
+                    // Only lists have a dotted tail.
+                    Some((Expression::Identifier(id, dot_span), _))
+                        if id.as_str() == "." && *opener != Token::LParen =>
+                    {
+                        let message = format!("unexpected '.' in '{}'", opener);
+                        return Err(SchemeError::syntax(&message).at(dot_span));
+                    }
+                    Some((expr, _)) => elements.push(expr),
+                    None => return Err(unclosed()),
+                },
+            }
+        }
+    }
+
+    fn single(&mut self) -> Result<Option<(Expression, Span)>, SchemeError> {
+        if self.depth == MAX_DEPTH {
+            let span = match self.iter.peek() {
+                Some(Ok((_, span))) => Some(*span),
+                _ => None,
+            };
+            let err = SchemeError::syntax("data nested too deeply");
+            return Err(match span {
+                Some(span) => err.at(span),
+                None => err,
+            });
+        }
+        self.depth += 1;
+        let result = self.datum();
+        self.depth -= 1;
+        result
+    }
+
+    fn datum(&mut self) -> Result<Option<(Expression, Span)>, SchemeError> {
+        let (token, span) = loop {
+            match self.iter.next() {
+                Some(Ok((Token::DatumComment, span))) => self.skip_datum(span)?,
+                Some(token) => break token?,
+                None => return Ok(None),
+            }
+        };
+        let expr = match token {
+            Token::LParen => {
+                let (elements, span) = self.elements(&token, span)?;
+                return Ok(Some((Expression::Combination(elements.into(), span), span)));
+            }
+            Token::VectorStart => {
+                let (elements, span) = self.elements(&token, span)?;
+                return Ok(Some((
+                    Expression::Vector(Rc::new(RefCell::new(elements))),
+                    span,
+                )));
+            }
+            Token::BytevectorStart => {
+                let (elements, span) = self.elements(&token, span)?;
+                return Ok(Some((bytevector(&elements, span)?, span)));
+            }
+            Token::RParen => return Err(SchemeError::syntax("unexpected ')'").at(span)),
+            Token::Quote => return self.abbreviation("quote", span),
+            Token::Quasiquote => return self.abbreviation("quasiquote", span),
+            Token::Unquote => return self.abbreviation("unquote", span),
+            Token::UnquoteSplicing => return self.abbreviation("unquote-splicing", span),
+            Token::DatumComment => unreachable!("datum comments are skipped above"),
+            Token::Identifier(id) => Expression::Identifier(Symbol::new(&id), span),
+            Token::StringLiteral(st) => Expression::string(st),
+            Token::NumberLiteral(v) => Expression::NumberLiteral(v),
+            Token::BooleanLiteral(b) => Expression::BooleanLiteral(b),
+            Token::CharLiteral(c) => Expression::CharLiteral(c),
+        };
+        Ok(Some((expr, span)))
+    }
 }
 
-impl<I: Iterator<Item = Token>> Iterator for Parser<I> {
-    type Item = Result<Expression, String>;
+impl<I: Iterator<Item = Result<(Token, Span), SchemeError>>> Iterator for Parser<I> {
+    type Item = Result<Expression, SchemeError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.single().transpose()
+        let result = self.single();
+        if result.is_err() && self.open > 0 {
+            self.resynchronize();
+        }
+        result.map(|e| e.map(|(expr, _)| expr)).transpose()
+    }
+}
+
+/// Builds a bytevector from the elements of a `#u8(...)` literal.
+fn bytevector(elements: &[Expression], span: Span) -> Result<Expression, SchemeError> {
+    let bytes = elements
+        .iter()
+        .map(|e| match e {
+            Expression::NumberLiteral(Number::Int(n)) => u8::try_from(*n).ok(),
+            _ => None,
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| SchemeError::syntax("bytevector elements must be bytes").at(span))?;
+    Ok(Expression::Bytevector(Rc::new(RefCell::new(bytes))))
+}
+
+/// Parses all of `source`, identified by `id`, collecting every error rather
+/// than stopping at the first.
+pub fn parse(source: &str, id: SourceId) -> Result<Vec<Expression>, Vec<SchemeError>> {
+    let (exprs, errors): (Vec<_>, Vec<_>) =
+        Parser::new(tokenize(source.chars(), id)).partition(Result::is_ok);
+    if errors.is_empty() {
+        Ok(exprs.into_iter().map(Result::unwrap).collect())
+    } else {
+        Err(errors.into_iter().map(Result::unwrap_err).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+    use crate::error::ErrorKind;
+    use crate::span::SourceId;
+
+    #[test]
+    fn reports_unclosed_opener() {
+        let errors = parse("(define (f x)\n  (+ x 1)", SourceId::fresh()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0].kind(), ErrorKind::Incomplete(_)));
+        assert_eq!(errors[0].span().unwrap().line, 1);
+        assert_eq!(errors[0].span().unwrap().column, 1);
+    }
+
+    #[test]
+    fn recovers_at_next_top_level_form() {
+        let source = "(a #foo b)\n(c d)\n)\n(e #\\bogus\n  f)\n(g";
+        let errors = parse(source, SourceId::fresh()).unwrap_err();
+        let lines: Vec<usize> = errors.iter().map(|e| e.span().unwrap().line).collect();
+        assert_eq!(lines, vec![1, 3, 4, 6]);
+        assert!(parse("(a) (b (c)) d", SourceId::fresh()).is_ok());
+    }
+
+    #[test]
+    fn rejects_dot_outside_lists() {
+        for source in ["#(1 . 2)", "#u8(1 . 2)"] {
+            let errors = parse(source, SourceId::fresh()).unwrap_err();
+            assert!(matches!(errors[0].kind(), ErrorKind::Syntax(_)));
+            assert_eq!(
+                errors[0].span().unwrap().column,
+                source.find('.').unwrap() + 1
+            );
+        }
+        assert!(parse("(1 . 2)", SourceId::fresh()).is_ok());
+    }
+
+    #[test]
+    fn never_panics() {
+        for source in [
+            ")", "(", "#(", "#u8(1 x)", "'", "(#;)", "#;", "\"\\x", "#\\",
+        ] {
+            assert!(
+                parse(source, SourceId::fresh()).is_err(),
+                "{:?} should not parse",
+                source
+            );
+        }
+        let deep = "(".repeat(100000) + &")".repeat(100000);
+        assert!(matches!(
+            parse(&deep, SourceId::fresh()).unwrap_err()[0].kind(),
+            ErrorKind::Syntax(_)
+        ));
     }
 }
@@ -0,0 +1,204 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::bigint::BigInt;
+
+/// An exact fraction in lowest terms with a positive denominator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rational {
+    numerator: BigInt,
+    denominator: BigInt,
+}
+
+impl Rational {
+    /// Returns the fraction `numerator / denominator` in lowest terms.
+    ///
+    /// Panics if `denominator` is zero.
+    pub fn new(numerator: BigInt, denominator: BigInt) -> Self {
+        assert!(!denominator.is_zero(), "rational with zero denominator");
+        let mut divisor = numerator.gcd(&denominator);
+        if denominator.is_negative() {
+            divisor = -&divisor;
+        }
+        Rational {
+            numerator: numerator.div_rem(&divisor).0,
+            denominator: denominator.div_rem(&divisor).0,
+        }
+    }
+
+    pub fn numerator(&self) -> &BigInt {
+        &self.numerator
+    }
+
+    pub fn denominator(&self) -> &BigInt {
+        &self.denominator
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator == BigInt::from(1)
+    }
+
+    /// Divides by `other`, returning `None` if it is zero.
+    pub fn checked_div(&self, other: &Rational) -> Option<Rational> {
+        if other.numerator.is_zero() {
+            return None;
+        }
+        Some(Rational::new(
+            &self.numerator * &other.denominator,
+            &self.denominator * &other.numerator,
+        ))
+    }
+
+    /// The largest integer not greater than the fraction.
+    pub fn floor(&self) -> BigInt {
+        let (quotient, remainder) = self.numerator.div_rem(&self.denominator);
+        if remainder.is_negative() {
+            &quotient - &BigInt::from(1)
+        } else {
+            quotient
+        }
+    }
+
+    /// Returns the nearest `f64`.
+    pub fn to_f64(&self) -> f64 {
+        let (n, d) = (self.numerator.to_f64(), self.denominator.to_f64());
+        if n.is_finite() && d.is_finite() {
+            return n / d;
+        }
+        // Scale the quotient to about 64 significant bits, so that neither
+        // part of the division is out of range.
+        let shift = self.numerator.bits() as i64 - self.denominator.bits() as i64 - 64;
+        let quotient = if shift >= 0 {
+            self.numerator
+                .div_rem(&self.denominator.shl(shift as u64))
+                .0
+        } else {
+            self.numerator
+                .shl(-shift as u64)
+                .div_rem(&self.denominator)
+                .0
+        };
+        // Apply the scale in two steps, as 2^shift alone may be out of range.
+        let half = (shift / 2) as f64;
+        quotient.to_f64() * 2f64.powf(half) * 2f64.powf(shift as f64 - half)
+    }
+
+    /// Returns the exact value of a finite `f64`.
+    pub fn from_f64(v: f64) -> Option<Self> {
+        if !v.is_finite() {
+            return None;
+        }
+        let bits = v.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = (bits & ((1 << 52) - 1)) as i64;
+        let (mantissa, exponent) = match exponent {
+            0 => (fraction, -1074),
+            _ => (fraction | (1 << 52), exponent - 1075),
+        };
+        let mantissa = BigInt::from(if v < 0.0 { -mantissa } else { mantissa });
+        Some(if exponent >= 0 {
+            Rational::new(mantissa.shl(exponent as u64), BigInt::from(1))
+        } else {
+            Rational::new(mantissa, BigInt::from(1).shl(-exponent as u64))
+        })
+    }
+}
+
+impl From<BigInt> for Rational {
+    fn from(v: BigInt) -> Self {
+        Rational {
+            numerator: v,
+            denominator: BigInt::from(1),
+        }
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.numerator * &other.denominator).cmp(&(&other.numerator * &self.denominator))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for &Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        Rational {
+            numerator: -&self.numerator,
+            denominator: self.denominator.clone(),
+        }
+    }
+}
+
+impl Add for &Rational {
+    type Output = Rational;
+    fn add(self, other: &Rational) -> Rational {
+        Rational::new(
+            &(&self.numerator * &other.denominator) + &(&other.numerator * &self.denominator),
+            &self.denominator * &other.denominator,
+        )
+    }
+}
+
+impl Sub for &Rational {
+    type Output = Rational;
+    fn sub(self, other: &Rational) -> Rational {
+        self + &-other
+    }
+}
+
+impl Mul for &Rational {
+    type Output = Rational;
+    fn mul(self, other: &Rational) -> Rational {
+        Rational::new(
+            &self.numerator * &other.numerator,
+            &self.denominator * &other.denominator,
+        )
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Rational;
+    use crate::bigint::BigInt;
+
+    fn ratio(n: i64, d: i64) -> Rational {
+        Rational::new(BigInt::from(n), BigInt::from(d))
+    }
+
+    #[test]
+    fn lowest_terms() {
+        assert_eq!(ratio(6, -4).to_string(), "-3/2");
+        assert_eq!((&ratio(1, 6) + &ratio(1, 3)).to_string(), "1/2");
+        assert_eq!(
+            ratio(1, 2).checked_div(&ratio(-1, 4)).unwrap().to_string(),
+            "-2/1"
+        );
+        assert_eq!(ratio(1, 2).checked_div(&ratio(0, 1)), None);
+        assert!(ratio(1, 3) < ratio(1, 2));
+    }
+
+    #[test]
+    fn float_conversions() {
+        assert_eq!(Rational::from_f64(0.75), Some(ratio(3, 4)));
+        assert_eq!(Rational::from_f64(-6.0), Some(ratio(-6, 1)));
+        assert_eq!(Rational::from_f64(f64::NAN), None);
+        assert_eq!(ratio(1, 3).to_f64(), 1.0 / 3.0);
+        let tiny = Rational::from_f64(f64::MIN_POSITIVE / 8.0).unwrap();
+        assert_eq!(tiny.to_f64(), f64::MIN_POSITIVE / 8.0);
+        let huge = Rational::new(BigInt::from(10).pow(400), BigInt::from(10).pow(399));
+        assert_eq!(huge.to_f64(), 10.0);
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Identifies a piece of source text, such as a file or one REPL input, so
+/// that spans are only excerpted against the text they came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceId(usize);
+
+impl SourceId {
+    /// Returns an id different from all others returned so far.
+    pub fn fresh() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        SourceId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A region of source text. `start` and `end` are byte offsets, while `line`
+/// and `column` (both starting at 1) locate `start` for display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub source: SourceId,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Returns the span covering both `self` and the later span `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+
+    /// Renders the source line containing the start of the span, with the
+    /// spanned part underlined by carets. Spans reaching past the end of the
+    /// line are underlined up to the end of the line. Returns `None` if the
+    /// span does not fit `source`.
+    pub fn excerpt(&self, source: &str) -> Option<String> {
+        let start = self.start;
+        if !source.is_char_boundary(start) {
+            return None;
+        }
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = &source[line_start..line_end];
+        let end = self.end.clamp(start, line_end);
+        if !source.is_char_boundary(end) {
+            return None;
+        }
+        let indent = source[line_start..start].chars().count();
+        let width = source[start..end].chars().count().max(1);
+        let gutter = self.line.to_string();
+        Some(format!(
+            "{} | {}\n{} | {}{}",
+            gutter,
+            line,
+            " ".repeat(gutter.len()),
+            " ".repeat(indent),
+            "^".repeat(width)
+        ))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SourceId, Span};
+
+    #[test]
+    fn excerpt_underlines_span() {
+        let source = "(define x 1)\n(foo (bar x))\n";
+        let span = Span {
+            source: SourceId::fresh(),
+            start: 18,
+            end: 25,
+            line: 2,
+            column: 6,
+        };
+        assert_eq!(
+            span.excerpt(source).unwrap(),
+            "2 | (foo (bar x))\n  |      ^^^^^^^"
+        );
+        assert_eq!(span.excerpt(&format!("a{}", "\u{e9}".repeat(10))), None);
+        assert_eq!(span.excerpt("(foo)"), None);
+    }
+}
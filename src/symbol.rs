@@ -0,0 +1,89 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+thread_local! {
+    static INTERNED: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+    static GENSYM_COUNTER: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A symbol name. Interned symbols with the same name share their allocation,
+/// so equality and hashing only look at the pointer.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+    /// Returns the interned symbol with the given name.
+    pub fn new(name: &str) -> Self {
+        INTERNED.with(|interned| {
+            let mut interned = interned.borrow_mut();
+            if let Some(existing) = interned.get(name) {
+                return Symbol(existing.clone());
+            }
+            let name: Rc<str> = Rc::from(name);
+            interned.insert(name.clone());
+            Symbol(name)
+        })
+    }
+
+    /// Returns a symbol that is distinct from every other symbol, even those
+    /// with the same name.
+    pub fn uninterned(name: &str) -> Self {
+        Symbol(Rc::from(name))
+    }
+
+    /// Returns a fresh uninterned symbol with a generated name.
+    pub fn gensym(prefix: &str) -> Self {
+        let count = GENSYM_COUNTER.with(|counter| {
+            let count = counter.get() + 1;
+            counter.set(count);
+            count
+        });
+        Self::uninterned(&format!("{}{}", prefix, count))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0.as_ptr() as usize).hash(state);
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Symbol;
+
+    #[test]
+    fn interning() {
+        assert_eq!(Symbol::new("abc"), Symbol::new("abc"));
+        assert_ne!(Symbol::new("abc"), Symbol::new("abd"));
+        assert_ne!(Symbol::uninterned("abc"), Symbol::new("abc"));
+        assert_ne!(Symbol::gensym("g"), Symbol::gensym("g"));
+    }
+}
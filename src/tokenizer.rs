@@ -2,15 +2,30 @@ use std::fmt;
 use std::iter::Peekable;
 use std::str::FromStr;
 
+use crate::error::SchemeError;
+use crate::expression::{write_string_literal, CHARACTER_NAMES};
 use crate::number::Number;
+use crate::span::{SourceId, Span};
 
 #[derive(Debug, PartialEq)]
 pub enum Token {
     LParen,
     RParen,
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+    /// `#;`, which comments out the datum that follows it.
+    DatumComment,
+    /// `#(`, which opens a vector literal.
+    VectorStart,
+    /// `#u8(`, which opens a bytevector literal.
+    BytevectorStart,
     Identifier(String),
     StringLiteral(String),
     NumberLiteral(Number),
+    BooleanLiteral(bool),
+    CharLiteral(char),
 }
 
 impl fmt::Display for Token {
@@ -18,82 +33,294 @@ impl fmt::Display for Token {
         match self {
             Token::LParen => write!(f, "("),
             Token::RParen => write!(f, ")"),
+            Token::Quote => write!(f, "'"),
+            Token::Quasiquote => write!(f, "`"),
+            Token::Unquote => write!(f, ","),
+            Token::UnquoteSplicing => write!(f, ",@"),
+            Token::DatumComment => write!(f, "#;"),
+            Token::VectorStart => write!(f, "#("),
+            Token::BytevectorStart => write!(f, "#u8("),
             Token::Identifier(id) => write!(f, "{}", id),
-            Token::StringLiteral(s) => write!(f, "\"{}\"", s),
+            Token::StringLiteral(s) => write_string_literal(f, s),
             Token::NumberLiteral(v) => write!(f, "{}", v),
+            Token::BooleanLiteral(b) => write!(f, "#{}", if *b { "t" } else { "f" }),
+            Token::CharLiteral(c) => write!(f, "#\\{}", c),
         }
     }
 }
 
 pub struct Tokenizer<I: Iterator<Item = char>> {
+    source: SourceId,
     iter: Peekable<I>,
+    offset: usize,
+    line: usize,
+    column: usize,
 }
 
-impl<I: Iterator<Item = char>> Iterator for Tokenizer<I> {
-    type Item = Token;
+impl<I: Iterator<Item = char>> Tokenizer<I> {
+    fn advance(&mut self) -> Option<char> {
+        let c = self.iter.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Consumes characters up to the next delimiter.
+    fn take_until_delimiter(&mut self, word: &mut String) {
+        while let Some(&c) = self.iter.peek() {
+            if is_delimiter(c) {
+                break;
+            }
+            word.push(c);
+            self.advance();
+        }
+    }
+
+    /// Reads the rest of a character literal whose `#\\` has been consumed.
+    fn character(&mut self) -> Result<char, SchemeError> {
+        let first = self
+            .advance()
+            .ok_or_else(|| SchemeError::incomplete("expecting a character"))?;
+        let mut name = first.to_string();
+        self.take_until_delimiter(&mut name);
+        if name.chars().count() == 1 {
+            return Ok(first);
+        }
+        if let Some((_, c)) = CHARACTER_NAMES.iter().find(|(n, _)| *n == name) {
+            return Ok(*c);
+        }
+        name.strip_prefix('x')
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32)
+            .ok_or_else(|| SchemeError::syntax(&format!("unknown character '#\\{}'", name)))
+    }
+
+    /// Reads the rest of a string literal whose opening `"` has been consumed,
+    /// interpreting escape sequences. The whole literal is consumed even if an
+    /// escape sequence in it is invalid.
+    fn string(&mut self) -> Result<String, SchemeError> {
+        let unterminated = || SchemeError::incomplete("unterminated string");
+        let mut s = String::new();
+        let mut error = None;
         loop {
-            match self.iter.peek() {
-                Some(c) if c.is_whitespace() => {
-                    self.iter.next();
+            match self.advance().ok_or_else(unterminated)? {
+                '"' => return error.map_or(Ok(s), Err),
+                '\\' => match self.advance().ok_or_else(unterminated)? {
+                    'a' => s.push('\u{7}'),
+                    'b' => s.push('\u{8}'),
+                    't' => s.push('\t'),
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    c @ ('"' | '\\' | '|') => s.push(c),
+                    'x' => {
+                        let mut hex = String::new();
+                        loop {
+                            match self.advance().ok_or_else(unterminated)? {
+                                ';' => break,
+                                '"' => {
+                                    let message = format!("unterminated escape '\\x{}'", hex);
+                                    return Err(error.unwrap_or(SchemeError::syntax(&message)));
+                                }
+                                c => hex.push(c),
+                            }
+                        }
+                        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                            Some(c) => s.push(c),
+                            None => {
+                                let message = format!("invalid escape '\\x{};'", hex);
+                                error.get_or_insert(SchemeError::syntax(&message));
+                            }
+                        }
+                    }
+                    c if c == '\n' || c.is_whitespace() => {
+                        // A line continuation: skip the line break and the
+                        // whitespace surrounding it.
+                        let mut newline = c == '\n';
+                        while let Some(&c) = self.iter.peek() {
+                            if c == '\n' && !newline {
+                                newline = true;
+                            } else if c == '\n' || !c.is_whitespace() {
+                                break;
+                            }
+                            self.advance();
+                        }
+                        if !newline {
+                            let message = "whitespace after '\\' in string";
+                            error.get_or_insert(SchemeError::syntax(message));
+                        }
+                    }
+                    c => {
+                        let message = format!("unknown escape '\\{}'", c);
+                        error.get_or_insert(SchemeError::syntax(&message));
+                    }
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// Skips the rest of the current line, including the line break.
+    fn skip_line(&mut self) {
+        while self.advance().is_some_and(|c| c != '\n') {}
+    }
+
+    /// Skips the rest of a `#| ... |#` block comment whose opening `#|` has
+    /// been consumed. Block comments nest. Returns `false` if the input ends
+    /// before the comment is closed.
+    fn skip_block_comment(&mut self) -> bool {
+        let mut depth = 1;
+        while let Some(c) = self.advance() {
+            match (c, self.iter.peek()) {
+                ('|', Some('#')) => {
+                    self.advance();
+                    depth -= 1;
+                    if depth == 0 {
+                        return true;
+                    }
                 }
-                _ => break,
+                ('#', Some('|')) => {
+                    self.advance();
+                    depth += 1;
+                }
+                _ => {}
             }
         }
+        false
+    }
+
+    /// Returns the span from the given start position to the current position.
+    fn span_from(&self, start: usize, line: usize, column: usize) -> Span {
+        Span {
+            source: self.source,
+            start,
+            end: self.offset,
+            line,
+            column,
+        }
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for Tokenizer<I> {
+    type Item = Result<(Token, Span), SchemeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (c, start, line, column) = loop {
+            while self.iter.peek().is_some_and(|c| c.is_whitespace()) {
+                self.advance();
+            }
 
-        match self.iter.next() {
-            Some('(') => Some(Token::LParen),
-            Some(')') => Some(Token::RParen),
-            Some('"') => {
-                let mut s = String::new();
-                loop {
-                    match self.iter.next() {
-                        Some('"') | None => break,
-                        Some(c) => s.push(c),
+            let (start, line, column) = (self.offset, self.line, self.column);
+            match self.advance()? {
+                ';' => self.skip_line(),
+                '#' if start == 0 && self.iter.peek() == Some(&'!') => {
+                    // A shebang line at the very start of a script.
+                    self.skip_line();
+                }
+                '#' if self.iter.peek() == Some(&'|') => {
+                    self.advance();
+                    if !self.skip_block_comment() {
+                        let span = self.span_from(start, line, column);
+                        return Some(Err(
+                            SchemeError::incomplete("unterminated block comment").at(span)
+                        ));
                     }
                 }
-                Some(Token::StringLiteral(s))
+                '#' if self.iter.peek() == Some(&';') => {
+                    self.advance();
+                    let span = self.span_from(start, line, column);
+                    return Some(Ok((Token::DatumComment, span)));
+                }
+                c => break (c, start, line, column),
             }
-            Some(c) => {
+        };
+        let token = match c {
+            '#' if self.iter.peek() == Some(&'(') => {
+                self.advance();
+                Token::VectorStart
+            }
+            '#' if self.iter.peek() == Some(&'\\') => {
+                self.advance();
+                match self.character() {
+                    Ok(c) => Token::CharLiteral(c),
+                    Err(err) => return Some(Err(err.at(self.span_from(start, line, column)))),
+                }
+            }
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '\'' => Token::Quote,
+            '`' => Token::Quasiquote,
+            ',' => {
+                if self.iter.peek() == Some(&'@') {
+                    self.advance();
+                    Token::UnquoteSplicing
+                } else {
+                    Token::Unquote
+                }
+            }
+            '"' => match self.string() {
+                Ok(s) => Token::StringLiteral(s),
+                Err(err) => return Some(Err(err.at(self.span_from(start, line, column)))),
+            },
+            c => {
                 let mut id = c.to_string();
-                loop {
-                    match self.iter.peek() {
-                        Some('(') | Some(')') => break,
-                        Some(c2) if c2.is_whitespace() => break,
-                        Some(c2) => {
-                            id.push(*c2);
-                            self.iter.next();
+                self.take_until_delimiter(&mut id);
+                match id.as_str() {
+                    "#t" | "#true" => Token::BooleanLiteral(true),
+                    "#f" | "#false" => Token::BooleanLiteral(false),
+                    "#u8" if self.iter.peek() == Some(&'(') => {
+                        self.advance();
+                        Token::BytevectorStart
+                    }
+                    _ => {
+                        if let Ok(v) = Number::from_str(id.as_str()) {
+                            Token::NumberLiteral(v)
+                        } else if id.starts_with('#') {
+                            let span = self.span_from(start, line, column);
+                            let message = format!("unknown syntax '{}'", id);
+                            return Some(Err(SchemeError::syntax(&message).at(span)));
+                        } else {
+                            Token::Identifier(id)
                         }
-                        None => break,
                     }
                 }
-                if let Ok(v) = Number::from_str(id.as_str()) {
-                    Some(Token::NumberLiteral(v))
-                } else {
-                    Some(Token::Identifier(id))
-                }
             }
-            None => None,
-        }
+        };
+        Some(Ok((token, self.span_from(start, line, column))))
     }
 }
 
-pub fn tokenize<I: Iterator<Item = char>>(iter: I) -> Tokenizer<I> {
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | ';' | '"')
+}
+
+pub fn tokenize<I: Iterator<Item = char>>(iter: I, source: SourceId) -> Tokenizer<I> {
     Tokenizer {
+        source,
         iter: iter.peekable(),
+        offset: 0,
+        line: 1,
+        column: 1,
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::{tokenize, Token};
+    use crate::error::ErrorKind;
     use crate::number::Number;
+    use crate::span::{SourceId, Span};
 
     #[test]
     fn basics() {
         let input = "(quote (testing 1 (2.0) -3.14e159))";
-        let tokens: Vec<Token> = tokenize(input.chars()).collect();
+        let tokens: Vec<Token> = tokenize(input.chars(), SourceId::fresh())
+            .map(|t| t.unwrap().0)
+            .collect();
         assert_eq!(
             vec![
                 Token::LParen,
@@ -111,4 +338,208 @@ mod test {
             tokens
         );
     }
+
+    #[test]
+    fn quote_abbreviations() {
+        let input = "'a `(b ,c ,@d)";
+        let tokens: Vec<Token> = tokenize(input.chars(), SourceId::fresh())
+            .map(|t| t.unwrap().0)
+            .collect();
+        assert_eq!(
+            vec![
+                Token::Quote,
+                Token::Identifier("a".to_string()),
+                Token::Quasiquote,
+                Token::LParen,
+                Token::Identifier("b".to_string()),
+                Token::Unquote,
+                Token::Identifier("c".to_string()),
+                Token::UnquoteSplicing,
+                Token::Identifier("d".to_string()),
+                Token::RParen
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn shebang_line() {
+        let input = "#!/usr/bin/env scheme\n(a)";
+        let tokens: Vec<Token> = tokenize(input.chars(), SourceId::fresh())
+            .map(|t| t.unwrap().0)
+            .collect();
+        assert_eq!(
+            vec![
+                Token::LParen,
+                Token::Identifier("a".to_string()),
+                Token::RParen
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let input = "a ; line comment\nb #| block #| nested |# |# c;d\n#;e";
+        let tokens: Vec<Token> = tokenize(input.chars(), SourceId::fresh())
+            .map(|t| t.unwrap().0)
+            .collect();
+        assert_eq!(
+            vec![
+                Token::Identifier("a".to_string()),
+                Token::Identifier("b".to_string()),
+                Token::Identifier("c".to_string()),
+                Token::DatumComment,
+                Token::Identifier("e".to_string())
+            ],
+            tokens
+        );
+        assert!(matches!(
+            tokenize("#| open #| |#".chars(), SourceId::fresh())
+                .next()
+                .unwrap()
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Incomplete(_)
+        ));
+    }
+
+    #[test]
+    fn number_prefixes() {
+        let input = "#xff #b-101 #e1.5 #i1/2 #x#e10 +inf.0 inf nan";
+        let tokens: Vec<Token> = tokenize(input.chars(), SourceId::fresh())
+            .map(|t| t.unwrap().0)
+            .collect();
+        assert_eq!(
+            vec![
+                Token::NumberLiteral(Number::from(255)),
+                Token::NumberLiteral(Number::from(-5)),
+                Token::NumberLiteral(Number::from(1.5).to_exact().unwrap()),
+                Token::NumberLiteral(Number::from(0.5)),
+                Token::NumberLiteral(Number::from(16)),
+                Token::NumberLiteral(Number::from(f64::INFINITY)),
+                Token::Identifier("inf".to_string()),
+                Token::Identifier("nan".to_string()),
+            ],
+            tokens
+        );
+        assert!(tokenize("#e+inf.0".chars(), SourceId::fresh())
+            .next()
+            .unwrap()
+            .is_err());
+        assert!(tokenize("#x#x1".chars(), SourceId::fresh())
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn hash_literals() {
+        let input = "#t #false #\\a #\\space #\\x41 #\\( #(1) #u8(2)";
+        let tokens: Vec<Token> = tokenize(input.chars(), SourceId::fresh())
+            .map(|t| t.unwrap().0)
+            .collect();
+        assert_eq!(
+            vec![
+                Token::BooleanLiteral(true),
+                Token::BooleanLiteral(false),
+                Token::CharLiteral('a'),
+                Token::CharLiteral(' '),
+                Token::CharLiteral('A'),
+                Token::CharLiteral('('),
+                Token::VectorStart,
+                Token::NumberLiteral(Number::from(1)),
+                Token::RParen,
+                Token::BytevectorStart,
+                Token::NumberLiteral(Number::from(2)),
+                Token::RParen
+            ],
+            tokens
+        );
+        assert!(matches!(
+            tokenize("#\\bogus".chars(), SourceId::fresh())
+                .next()
+                .unwrap()
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Syntax(_)
+        ));
+        assert!(matches!(
+            tokenize("#foo".chars(), SourceId::fresh())
+                .next()
+                .unwrap()
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Syntax(_)
+        ));
+    }
+
+    #[test]
+    fn string_escapes() {
+        let input = "\"say \\\"hi\\\"\\n\\t\\x41;\\\\ \\  \n   end\"";
+        let tokens: Vec<Token> = tokenize(input.chars(), SourceId::fresh())
+            .map(|t| t.unwrap().0)
+            .collect();
+        assert_eq!(
+            vec![Token::StringLiteral("say \"hi\"\n\tA\\ end".to_string())],
+            tokens
+        );
+        assert!(matches!(
+            tokenize("\"\\q\"".chars(), SourceId::fresh())
+                .next()
+                .unwrap()
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Syntax(_)
+        ));
+    }
+
+    #[test]
+    fn token_spans() {
+        let input = "(a\n  \"bc\")";
+        let source = SourceId::fresh();
+        let spans: Vec<Span> = tokenize(input.chars(), source)
+            .map(|t| t.unwrap().1)
+            .collect();
+        assert_eq!(
+            vec![
+                Span {
+                    source,
+                    start: 0,
+                    end: 1,
+                    line: 1,
+                    column: 1
+                },
+                Span {
+                    source,
+                    start: 1,
+                    end: 2,
+                    line: 1,
+                    column: 2
+                },
+                Span {
+                    source,
+                    start: 5,
+                    end: 9,
+                    line: 2,
+                    column: 3
+                },
+                Span {
+                    source,
+                    start: 9,
+                    end: 10,
+                    line: 2,
+                    column: 7
+                },
+            ],
+            spans
+        );
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let mut tokens = tokenize("\"abc".chars(), SourceId::fresh());
+        let err = tokens.next().unwrap().unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::Incomplete(_)));
+    }
 }